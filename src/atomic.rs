@@ -0,0 +1,752 @@
+//! `AtomicTrc<T>` and `AtomicOptionTrc<T>`, cells holding a `SharedTrc<T>` which can be loaded and replaced
+//! atomically from many threads.
+//!
+//! A cell owns one atomic reference to the value it holds. To load it, a thread must add a reference before a writer
+//! releases the one owned by the cell. Like `arc-swap`, the loading thread records the pointer it read in its debt
+//! slot, and checks that the cell still holds it. Until the slot is cleared, the value is borrowed from the cell. A
+//! writer which removes a value pays the debts recorded for it, by adding a reference on behalf of each borrower, before
+//! releasing the reference of the cell. A borrower then adds its own reference, and clears its slot if the debt was not
+//! paid yet. No operation waits for another thread: a load only retries if the cell was changed in the meantime.
+//!
+//! A thread which loads a cell often can keep the `Trc<T>` it loaded in an `AtomicTrcCache<T>` or
+//! `AtomicOptionTrcCache<T>`. While the cell still holds the same pointer, loading from the cache returns the cached
+//! `Trc<T>` without touching the reference counts. The cache borrows the cell, and the replaced value is only kept
+//! alive until the cache is loaded again or dropped.
+
+use std::{
+    cell::Cell,
+    fmt::Debug,
+    marker::PhantomData,
+    mem::{align_of, ManuallyDrop},
+    ptr::{self, NonNull},
+    sync::atomic::{AtomicBool, AtomicPtr, Ordering},
+};
+
+use allocator_api2::alloc::Global;
+
+use crate::{data_offset, sub_value, sum_value, SharedTrc, SharedTrcInternal, Trc, MAX_REFCOUNT};
+
+/// Slot in which a thread records the pointer to a value it borrows from a cell. Slots are never freed, and are reused
+/// by other threads after their thread exits.
+struct DebtSlot {
+    /// Pointer to the `SharedTrcInternal` which is borrowed, or null.
+    debt: AtomicPtr<u8>,
+    in_use: AtomicBool,
+    next: *const DebtSlot,
+}
+
+/// Head of the list of all debt slots.
+static SLOTS: AtomicPtr<DebtSlot> = AtomicPtr::new(ptr::null_mut());
+
+/// Iterate over all debt slots.
+fn slots() -> impl Iterator<Item = &'static DebtSlot> {
+    let mut slot = SLOTS.load(Ordering::Acquire) as *const DebtSlot;
+    std::iter::from_fn(move || {
+        let current = unsafe { slot.as_ref() }?;
+        slot = current.next;
+        Some(current)
+    })
+}
+
+/// Claim a debt slot which is not in use, or add a new one.
+fn acquire_slot() -> &'static DebtSlot {
+    for slot in slots() {
+        if !slot.in_use.load(Ordering::Relaxed)
+            && slot
+                .in_use
+                .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+                .is_ok()
+        {
+            return slot;
+        }
+    }
+
+    let slot = Box::leak(Box::new(DebtSlot {
+        debt: AtomicPtr::new(ptr::null_mut()),
+        in_use: AtomicBool::new(true),
+        next: ptr::null(),
+    }));
+    let mut head = SLOTS.load(Ordering::Relaxed);
+    loop {
+        slot.next = head;
+        match SLOTS.compare_exchange_weak(head, slot, Ordering::Release, Ordering::Relaxed) {
+            Ok(_) => return slot,
+            Err(actual) => head = actual,
+        }
+    }
+}
+
+/// The debt slot claimed by a thread, which is released when the thread exits.
+struct LocalSlot(Cell<Option<&'static DebtSlot>>);
+
+impl Drop for LocalSlot {
+    fn drop(&mut self) {
+        if let Some(slot) = self.0.get() {
+            slot.in_use.store(false, Ordering::Release);
+        }
+    }
+}
+
+thread_local! {
+    static LOCAL_SLOT: LocalSlot = const { LocalSlot(Cell::new(None)) };
+}
+
+/// Run `f` with a debt slot which no other thread uses.
+#[inline]
+fn with_slot<R>(f: impl FnOnce(&DebtSlot) -> R) -> R {
+    let slot = LOCAL_SLOT.try_with(|local| match local.0.get() {
+        Some(slot) => slot,
+        None => {
+            let slot = acquire_slot();
+            local.0.set(Some(slot));
+            slot
+        }
+    });
+
+    match slot {
+        Ok(slot) => f(slot),
+        //The thread local storage is being destroyed, so a slot is claimed for this call only
+        Err(_) => {
+            let slot = acquire_slot();
+            let res = f(slot);
+            slot.in_use.store(false, Ordering::Release);
+            res
+        }
+    }
+}
+
+/// Add an atomic reference on behalf of every thread which borrows `ptr`, after it was removed from a cell. The cell
+/// still owns its reference, so the atomic reference count does not reach zero.
+fn pay_debts<T>(ptr: *mut SharedTrcInternal<T>) {
+    let Some(data) = NonNull::new(ptr) else {
+        return;
+    };
+
+    for slot in slots() {
+        if slot.debt.load(Ordering::SeqCst) != ptr.cast() {
+            continue;
+        }
+
+        increment(data);
+        if slot
+            .debt
+            .compare_exchange(
+                ptr.cast(),
+                ptr::null_mut(),
+                Ordering::AcqRel,
+                Ordering::Relaxed,
+            )
+            .is_err()
+        {
+            //The borrower added its own reference first
+            sub_value(&unsafe { data.as_ref() }.atomicref, 1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Add an atomic reference to a value which is kept alive by another reference.
+#[inline]
+fn increment<T>(data: NonNull<SharedTrcInternal<T>>) {
+    let prev = sum_value(&unsafe { data.as_ref() }.atomicref, 1, Ordering::Relaxed);
+    if prev > MAX_REFCOUNT {
+        panic!("Overflow of maximum strong reference count.");
+    }
+}
+
+/// Take the atomic reference owned by a cell for the pointer it stored.
+///
+/// # Safety
+/// `ptr` must have been removed from a cell, and its debts must have been paid.
+#[inline]
+unsafe fn reclaim<T>(ptr: *mut SharedTrcInternal<T>) -> Option<SharedTrc<T>> {
+    Some(SharedTrc {
+        data: NonNull::new(ptr)?,
+        alloc: Global,
+    })
+}
+
+/// `AtomicOptionTrc<T>` is a cell holding an optional [`SharedTrc<T>`], which can be loaded and replaced atomically
+/// from many threads. See [`AtomicTrc`] for a cell which always holds a value.
+///
+/// No operation takes a lock or waits for another thread. A thread which loads the cell often can keep the `Trc<T>`
+/// it loaded in an [`AtomicOptionTrcCache<T>`], created by [`AtomicOptionTrc::cache`].
+///
+/// ## Examples
+/// ```
+/// use trc::{AtomicOptionTrc, Trc};
+///
+/// let cell = AtomicOptionTrc::empty();
+/// assert!(cell.load().is_none());
+///
+/// cell.store(Some(Trc::new(String::from("config")).into()));
+/// assert_eq!(*cell.load().unwrap(), "config");
+/// ```
+pub struct AtomicOptionTrc<T> {
+    ptr: AtomicPtr<SharedTrcInternal<T>>,
+    phantom: PhantomData<Option<SharedTrc<T>>>,
+}
+
+unsafe impl<T: Sync + Send> Send for AtomicOptionTrc<T> {}
+unsafe impl<T: Sync + Send> Sync for AtomicOptionTrc<T> {}
+
+impl<T> AtomicOptionTrc<T> {
+    /// Create a new `AtomicOptionTrc<T>` holding the provided value.
+    ///
+    /// # Examples
+    /// ```
+    /// use trc::{AtomicOptionTrc, Trc};
+    ///
+    /// let cell = AtomicOptionTrc::new(Some(Trc::new(100).into()));
+    /// assert_eq!(*cell.load().unwrap(), 100);
+    /// ```
+    #[inline]
+    pub fn new(value: Option<SharedTrc<T>>) -> Self {
+        AtomicOptionTrc {
+            ptr: AtomicPtr::new(into_ptr(value)),
+            phantom: PhantomData,
+        }
+    }
+
+    /// Create a new empty `AtomicOptionTrc<T>`.
+    ///
+    /// # Examples
+    /// ```
+    /// use trc::AtomicOptionTrc;
+    ///
+    /// let cell = AtomicOptionTrc::<i32>::empty();
+    /// assert!(cell.load().is_none());
+    /// ```
+    #[inline]
+    pub const fn empty() -> Self {
+        AtomicOptionTrc {
+            ptr: AtomicPtr::new(ptr::null_mut()),
+            phantom: PhantomData,
+        }
+    }
+
+    /// Load the value as a `Trc<T>` local to this thread.
+    /// With the `registry` feature, if this thread already has a `Trc<T>` to the value, the returned `Trc<T>` shares
+    /// its local thread reference count, and the atomic reference count is left unchanged.
+    ///
+    /// # Examples
+    /// ```
+    /// use trc::{AtomicOptionTrc, Trc};
+    ///
+    /// let cell = AtomicOptionTrc::new(Some(Trc::new(100).into()));
+    /// let trc = cell.load().unwrap();
+    /// assert_eq!(*trc, 100);
+    /// ```
+    #[inline]
+    pub fn load(&self) -> Option<Trc<T>> {
+        self.load_shared().map(SharedTrc::to_trc)
+    }
+
+    /// Load the value as a `SharedTrc<T>`, which can be sent to other threads.
+    ///
+    /// # Examples
+    /// ```
+    /// use trc::{AtomicOptionTrc, Trc};
+    ///
+    /// let cell = AtomicOptionTrc::new(Some(Trc::new(100).into()));
+    /// let shared = cell.load_shared().unwrap();
+    /// assert_eq!(*shared, 100);
+    /// ```
+    pub fn load_shared(&self) -> Option<SharedTrc<T>> {
+        with_slot(|slot| {
+            let mut current = self.ptr.load(Ordering::Acquire);
+            loop {
+                let data = NonNull::new(current)?;
+
+                //Borrow the value, and check that the cell still owned it when the debt was recorded
+                slot.debt.store(current.cast(), Ordering::SeqCst);
+                let confirmed = self.ptr.load(Ordering::SeqCst);
+                if confirmed == current {
+                    increment(data);
+                }
+
+                if slot
+                    .debt
+                    .compare_exchange(
+                        current.cast(),
+                        ptr::null_mut(),
+                        Ordering::AcqRel,
+                        Ordering::Relaxed,
+                    )
+                    .is_err()
+                {
+                    //A writer paid the debt, so the value is owned either way
+                    if confirmed == current {
+                        sub_value(&unsafe { data.as_ref() }.atomicref, 1, Ordering::Relaxed);
+                    }
+                } else if confirmed != current {
+                    //The value was replaced before it was borrowed, so it might be freed already
+                    current = confirmed;
+                    continue;
+                }
+
+                return Some(SharedTrc {
+                    data,
+                    alloc: Global,
+                });
+            }
+        })
+    }
+
+    /// Create an [`AtomicOptionTrcCache<T>`] which keeps the `Trc<T>` it last loaded from this cell, so that loading
+    /// the same value again does not touch the reference counts.
+    ///
+    /// # Examples
+    /// ```
+    /// use trc::{AtomicOptionTrc, Trc};
+    ///
+    /// let cell = AtomicOptionTrc::new(Some(Trc::new(100).into()));
+    /// let mut cache = cell.cache();
+    /// assert_eq!(**cache.load().unwrap(), 100);
+    /// ```
+    #[inline]
+    pub fn cache(&self) -> AtomicOptionTrcCache<'_, T> {
+        AtomicOptionTrcCache {
+            cell: self,
+            cached: None,
+        }
+    }
+
+    /// Store a new value, dropping the previous one.
+    ///
+    /// # Examples
+    /// ```
+    /// use trc::{AtomicOptionTrc, Trc};
+    ///
+    /// let cell = AtomicOptionTrc::new(Some(Trc::new(100).into()));
+    /// cell.store(None);
+    /// assert!(cell.load().is_none());
+    /// ```
+    #[inline]
+    pub fn store(&self, value: Option<SharedTrc<T>>) {
+        drop(self.swap(value));
+    }
+
+    /// Store a new value, returning the previous one.
+    ///
+    /// # Examples
+    /// ```
+    /// use trc::{AtomicOptionTrc, Trc};
+    ///
+    /// let cell = AtomicOptionTrc::new(Some(Trc::new(100).into()));
+    /// let prev = cell.swap(Some(Trc::new(200).into()));
+    /// assert_eq!(*prev.unwrap(), 100);
+    /// assert_eq!(*cell.load().unwrap(), 200);
+    /// ```
+    #[inline]
+    pub fn swap(&self, value: Option<SharedTrc<T>>) -> Option<SharedTrc<T>> {
+        let new = into_ptr(value);
+        let prev = self.ptr.swap(new, Ordering::SeqCst);
+        pay_debts(prev);
+        unsafe { reclaim(prev) }
+    }
+
+    /// Store a new value if the current value is `current`, compared by address. On success, the previous value is
+    /// returned in [`Ok`]. Otherwise, the new value is returned in [`Err`].
+    ///
+    /// # Examples
+    /// ```
+    /// use trc::{AtomicOptionTrc, Trc};
+    ///
+    /// let cell = AtomicOptionTrc::new(Some(Trc::new(100).into()));
+    /// let current = cell.load().unwrap();
+    ///
+    /// let prev = cell.compare_and_swap(Some(&*current), Some(Trc::new(200).into()));
+    /// assert_eq!(*prev.ok().unwrap().unwrap(), 100);
+    ///
+    /// let rejected = cell.compare_and_swap(Some(&*current), None);
+    /// assert!(rejected.is_err());
+    /// assert_eq!(*cell.load().unwrap(), 200);
+    /// ```
+    pub fn compare_and_swap(
+        &self,
+        current: Option<&T>,
+        value: Option<SharedTrc<T>>,
+    ) -> Result<Option<SharedTrc<T>>, Option<SharedTrc<T>>> {
+        //The current value might be freed concurrently, so only addresses are compared
+        let expected = current.map_or(0, |current| current as *const T as usize);
+        let new = into_ptr(value);
+
+        let mut actual = self.ptr.load(Ordering::Relaxed);
+        loop {
+            let data_addr = if actual.is_null() {
+                0
+            } else {
+                actual as usize + data_offset(align_of::<T>())
+            };
+            if data_addr != expected {
+                return Err(unsafe { reclaim(new) });
+            }
+
+            match self
+                .ptr
+                .compare_exchange_weak(actual, new, Ordering::SeqCst, Ordering::Relaxed)
+            {
+                Ok(_) => {
+                    pay_debts(actual);
+                    return Ok(unsafe { reclaim(actual) });
+                }
+                Err(next) => actual = next,
+            }
+        }
+    }
+
+    /// Consume the `AtomicOptionTrc<T>`, returning the value.
+    ///
+    /// # Examples
+    /// ```
+    /// use trc::{AtomicOptionTrc, Trc};
+    ///
+    /// let cell = AtomicOptionTrc::new(Some(Trc::new(100).into()));
+    /// assert_eq!(*cell.into_inner().unwrap(), 100);
+    /// ```
+    #[inline]
+    pub fn into_inner(self) -> Option<SharedTrc<T>> {
+        let this = ManuallyDrop::new(self);
+        unsafe { reclaim(this.ptr.load(Ordering::Relaxed)) }
+    }
+}
+
+impl<T> Drop for AtomicOptionTrc<T> {
+    #[inline]
+    fn drop(&mut self) {
+        drop(unsafe { reclaim(*self.ptr.get_mut()) });
+    }
+}
+
+/// Take the atomic reference owned by a `SharedTrc<T>`, as the pointer which a cell stores for it.
+#[inline]
+fn into_ptr<T>(value: Option<SharedTrc<T>>) -> *mut SharedTrcInternal<T> {
+    match value {
+        Some(value) => ManuallyDrop::new(value).data.as_ptr(),
+        None => ptr::null_mut(),
+    }
+}
+
+impl<T> Default for AtomicOptionTrc<T> {
+    #[inline]
+    fn default() -> Self {
+        Self::empty()
+    }
+}
+
+impl<T> From<Option<SharedTrc<T>>> for AtomicOptionTrc<T> {
+    #[inline]
+    fn from(value: Option<SharedTrc<T>>) -> Self {
+        Self::new(value)
+    }
+}
+
+impl<T: Debug> Debug for AtomicOptionTrc<T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_tuple("AtomicOptionTrc")
+            .field(&self.load_shared())
+            .finish()
+    }
+}
+
+/// `AtomicOptionTrcCache<T>` keeps the [`Trc<T>`] which the current thread last loaded from an
+/// [`AtomicOptionTrc<T>`]. Loading from the cache compares the pointer held by the cell with the cached one, and only
+/// loads from the cell if the value was replaced. The cache holds a reference to the cached value, so no other value
+/// can be stored at the same address while it is cached.
+///
+/// The cache borrows the cell, and it is not [`Send`], so each thread creates its own. A value which was replaced in
+/// the cell is kept alive until the cache is loaded again or dropped.
+///
+/// ## Examples
+/// ```
+/// use trc::{AtomicOptionTrc, Trc};
+///
+/// let cell = AtomicOptionTrc::new(Some(Trc::new(String::from("v1")).into()));
+/// let mut cache = cell.cache();
+/// let first = cache.load().unwrap().clone();
+/// assert!(Trc::ptr_eq(&first, cache.load().unwrap()));
+///
+/// cell.store(None);
+/// assert!(cache.load().is_none());
+/// ```
+pub struct AtomicOptionTrcCache<'a, T> {
+    cell: &'a AtomicOptionTrc<T>,
+    cached: Option<Trc<T>>,
+}
+
+impl<T> AtomicOptionTrcCache<'_, T> {
+    /// Load the value from the cell, returning the cached `Trc<T>` if the cell still holds it.
+    ///
+    /// # Examples
+    /// ```
+    /// use trc::{AtomicOptionTrc, Trc};
+    ///
+    /// let cell = AtomicOptionTrc::new(Some(Trc::new(100).into()));
+    /// let mut cache = cell.cache();
+    /// assert_eq!(**cache.load().unwrap(), 100);
+    ///
+    /// cell.store(Some(Trc::new(200).into()));
+    /// assert_eq!(**cache.load().unwrap(), 200);
+    /// ```
+    #[inline]
+    pub fn load(&mut self) -> Option<&Trc<T>> {
+        let current = self.cell.ptr.load(Ordering::Acquire);
+        let cached = self
+            .cached
+            .as_ref()
+            .map_or(ptr::null_mut(), |cached| cached.shared.as_ptr());
+        if cached != current {
+            self.cached = self.cell.load();
+        }
+        self.cached.as_ref()
+    }
+}
+
+impl<T: Debug> Debug for AtomicOptionTrcCache<'_, T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_tuple("AtomicOptionTrcCache")
+            .field(&self.cached)
+            .finish()
+    }
+}
+
+/// `AtomicTrc<T>` is a cell holding a [`SharedTrc<T>`], which can be loaded and replaced atomically from many threads.
+/// Loading returns a [`Trc<T>`] local to the loading thread, so a value which is read often, such as a configuration
+/// that can be reloaded, can be read through the thread's local reference count afterwards. A thread which loads the
+/// cell often can keep the `Trc<T>` it loaded in an [`AtomicTrcCache<T>`], created by [`AtomicTrc::cache`].
+/// See [`AtomicOptionTrc`] for a cell which can be empty.
+///
+/// ## Examples
+/// ```
+/// use std::thread;
+/// use trc::{AtomicTrc, Trc};
+///
+/// static CONFIG: std::sync::OnceLock<AtomicTrc<String>> = std::sync::OnceLock::new();
+/// let config = CONFIG.get_or_init(|| AtomicTrc::new(Trc::new(String::from("v1")).into()));
+///
+/// let handle = thread::spawn(|| {
+///     let config = CONFIG.get().unwrap().load();
+///     assert!(config.starts_with('v'));
+/// });
+///
+/// config.store(Trc::new(String::from("v2")).into());
+/// handle.join().unwrap();
+/// assert_eq!(*config.load(), "v2");
+/// ```
+pub struct AtomicTrc<T> {
+    inner: AtomicOptionTrc<T>,
+}
+
+impl<T> AtomicTrc<T> {
+    /// Create a new `AtomicTrc<T>` holding the provided value.
+    ///
+    /// # Examples
+    /// ```
+    /// use trc::{AtomicTrc, Trc};
+    ///
+    /// let cell = AtomicTrc::new(Trc::new(100).into());
+    /// assert_eq!(*cell.load(), 100);
+    /// ```
+    #[inline]
+    pub fn new(value: SharedTrc<T>) -> Self {
+        AtomicTrc {
+            inner: AtomicOptionTrc::new(Some(value)),
+        }
+    }
+
+    /// Load the value as a `Trc<T>` local to this thread.
+    /// With the `registry` feature, if this thread already has a `Trc<T>` to the value, the returned `Trc<T>` shares
+    /// its local thread reference count, and the atomic reference count is left unchanged.
+    ///
+    /// # Examples
+    /// ```
+    /// use trc::{AtomicTrc, Trc};
+    ///
+    /// let cell = AtomicTrc::new(Trc::new(100).into());
+    /// let trc = cell.load();
+    /// assert_eq!(*trc, 100);
+    /// ```
+    #[inline]
+    pub fn load(&self) -> Trc<T> {
+        SharedTrc::to_trc(self.load_shared())
+    }
+
+    /// Load the value as a `SharedTrc<T>`, which can be sent to other threads.
+    ///
+    /// # Examples
+    /// ```
+    /// use trc::{AtomicTrc, Trc};
+    ///
+    /// let cell = AtomicTrc::new(Trc::new(100).into());
+    /// let shared = cell.load_shared();
+    /// assert_eq!(*shared, 100);
+    /// ```
+    #[inline]
+    pub fn load_shared(&self) -> SharedTrc<T> {
+        //An `AtomicTrc<T>` always holds a value
+        self.inner.load_shared().unwrap()
+    }
+
+    /// Create an [`AtomicTrcCache<T>`] which keeps the `Trc<T>` it last loaded from this cell, so that loading the
+    /// same value again does not touch the reference counts.
+    ///
+    /// # Examples
+    /// ```
+    /// use trc::{AtomicTrc, Trc};
+    ///
+    /// let cell = AtomicTrc::new(Trc::new(100).into());
+    /// let mut cache = cell.cache();
+    /// assert_eq!(**cache.load(), 100);
+    /// ```
+    #[inline]
+    pub fn cache(&self) -> AtomicTrcCache<'_, T> {
+        AtomicTrcCache {
+            inner: self.inner.cache(),
+        }
+    }
+
+    /// Store a new value, dropping the previous one.
+    ///
+    /// # Examples
+    /// ```
+    /// use trc::{AtomicTrc, Trc};
+    ///
+    /// let cell = AtomicTrc::new(Trc::new(100).into());
+    /// cell.store(Trc::new(200).into());
+    /// assert_eq!(*cell.load(), 200);
+    /// ```
+    #[inline]
+    pub fn store(&self, value: SharedTrc<T>) {
+        self.inner.store(Some(value));
+    }
+
+    /// Store a new value, returning the previous one.
+    ///
+    /// # Examples
+    /// ```
+    /// use trc::{AtomicTrc, Trc};
+    ///
+    /// let cell = AtomicTrc::new(Trc::new(100).into());
+    /// let prev = cell.swap(Trc::new(200).into());
+    /// assert_eq!(*prev, 100);
+    /// assert_eq!(*cell.load(), 200);
+    /// ```
+    #[inline]
+    pub fn swap(&self, value: SharedTrc<T>) -> SharedTrc<T> {
+        self.inner.swap(Some(value)).unwrap()
+    }
+
+    /// Store a new value if the current value is `current`, compared by address. On success, the previous value is
+    /// returned in [`Ok`]. Otherwise, the new value is returned in [`Err`].
+    ///
+    /// # Examples
+    /// ```
+    /// use trc::{AtomicTrc, Trc};
+    ///
+    /// let cell = AtomicTrc::new(Trc::new(100).into());
+    /// let current = cell.load();
+    ///
+    /// let prev = cell.compare_and_swap(&current, Trc::new(200).into());
+    /// assert_eq!(*prev.ok().unwrap(), 100);
+    ///
+    /// let rejected = cell.compare_and_swap(&current, Trc::new(300).into());
+    /// assert_eq!(*rejected.err().unwrap(), 300);
+    /// assert_eq!(*cell.load(), 200);
+    /// ```
+    #[inline]
+    pub fn compare_and_swap(
+        &self,
+        current: &T,
+        value: SharedTrc<T>,
+    ) -> Result<SharedTrc<T>, SharedTrc<T>> {
+        match self.inner.compare_and_swap(Some(current), Some(value)) {
+            Ok(prev) => Ok(prev.unwrap()),
+            Err(value) => Err(value.unwrap()),
+        }
+    }
+
+    /// Consume the `AtomicTrc<T>`, returning the value.
+    ///
+    /// # Examples
+    /// ```
+    /// use trc::{AtomicTrc, Trc};
+    ///
+    /// let cell = AtomicTrc::new(Trc::new(100).into());
+    /// assert_eq!(*cell.into_inner(), 100);
+    /// ```
+    #[inline]
+    pub fn into_inner(self) -> SharedTrc<T> {
+        self.inner.into_inner().unwrap()
+    }
+}
+
+impl<T> From<SharedTrc<T>> for AtomicTrc<T> {
+    #[inline]
+    fn from(value: SharedTrc<T>) -> Self {
+        Self::new(value)
+    }
+}
+
+impl<T: Default> Default for AtomicTrc<T> {
+    #[inline]
+    fn default() -> Self {
+        Self::new(SharedTrc::default())
+    }
+}
+
+impl<T: Debug> Debug for AtomicTrc<T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_tuple("AtomicTrc")
+            .field(&self.load_shared())
+            .finish()
+    }
+}
+
+/// `AtomicTrcCache<T>` keeps the [`Trc<T>`] which the current thread last loaded from an [`AtomicTrc<T>`]. Loading
+/// from the cache compares the pointer held by the cell with the cached one, and only loads from the cell if the value
+/// was replaced.
+///
+/// The cache borrows the cell, and it is not [`Send`], so each thread creates its own. A value which was replaced in
+/// the cell is kept alive until the cache is loaded again or dropped.
+///
+/// ## Examples
+/// ```
+/// use trc::{AtomicTrc, Trc};
+///
+/// let cell = AtomicTrc::new(Trc::new(String::from("v1")).into());
+/// let mut cache = cell.cache();
+/// assert_eq!(cache.load().as_str(), "v1");
+///
+/// cell.store(Trc::new(String::from("v2")).into());
+/// assert_eq!(cache.load().as_str(), "v2");
+/// ```
+pub struct AtomicTrcCache<'a, T> {
+    inner: AtomicOptionTrcCache<'a, T>,
+}
+
+impl<T> AtomicTrcCache<'_, T> {
+    /// Load the value from the cell, returning the cached `Trc<T>` if the cell still holds it.
+    ///
+    /// # Examples
+    /// ```
+    /// use trc::{AtomicTrc, Trc};
+    ///
+    /// let cell = AtomicTrc::new(Trc::new(100).into());
+    /// let mut cache = cell.cache();
+    /// let trc = cache.load().clone();
+    /// assert!(Trc::ptr_eq(&trc, cache.load()));
+    /// ```
+    #[inline]
+    pub fn load(&mut self) -> &Trc<T> {
+        //An `AtomicTrc<T>` always holds a value
+        self.inner.load().unwrap()
+    }
+}
+
+impl<T: Debug> Debug for AtomicTrcCache<'_, T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_tuple("AtomicTrcCache")
+            .field(&self.inner.cached)
+            .finish()
+    }
+}
@@ -0,0 +1,700 @@
+//! `CcTrc<T>`, a reference counted pointer whose unreachable cycles are reclaimed by [`collect_cycles`].
+//!
+//! The collector is the synchronous trial deletion algorithm of Bacon and Rajan. When a `CcTrc<T>` is dropped and the
+//! count stays above zero, the allocation may be the root of a garbage cycle, so it is buffered. [`collect_cycles`]
+//! subtracts the references held by the values reachable from the buffered roots, as reported by [`Trace`]. Any value
+//! whose count drops to zero is only referenced from inside the cycle, and is reclaimed.
+
+use std::{
+    alloc::Layout,
+    cell::{Cell, RefCell, UnsafeCell},
+    collections::VecDeque,
+    fmt::{Debug, Display},
+    marker::PhantomData,
+    mem,
+    ops::Deref,
+    ptr::{self, addr_of_mut, write, NonNull},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use allocator_api2::alloc::Global;
+
+use crate::{allocate_for_layout, SharedTrcInternal, Weak, MAX_REFCOUNT};
+
+/// A type-erased allocation of a `CcTrc<T>`.
+type Node = NonNull<SharedTrcInternal<dyn Trace>>;
+
+//The inline local count of a `CcTrc<T>` allocation holds the state of the collector instead
+const COLOR: usize = 0b11;
+/// In use, or free.
+const BLACK: usize = 0;
+/// Possibly a member of a garbage cycle.
+const GRAY: usize = 1;
+/// A member of a garbage cycle.
+const WHITE: usize = 2;
+/// Possibly the root of a garbage cycle.
+const PURPLE: usize = 3;
+/// In the buffer of possible roots, which holds a weak reference to the allocation.
+const BUFFERED: usize = 1 << 2;
+/// The value has been dropped.
+const DROPPED: usize = 1 << 3;
+
+/// Buffer of possible roots of garbage cycles. Roots still buffered when the thread exits are released.
+struct Roots(Vec<Node>);
+
+impl Drop for Roots {
+    fn drop(&mut self) {
+        for node in self.0.drain(..) {
+            unsafe { unbuffer(node) };
+        }
+    }
+}
+
+thread_local! {
+    static ROOTS: RefCell<Roots> = const { RefCell::new(Roots(Vec::new())) };
+    static COLLECTING: Cell<bool> = const { Cell::new(false) };
+}
+
+/// `Trace` reports the `CcTrc`s owned by a value to the cycle collector. It can be derived with `#[derive(Trace)]`,
+/// which requires the `derive` feature.
+///
+/// # Safety
+/// `trace` must pass every `CcTrc` owned by the value to [`Tracer`] exactly once, either directly or through the
+/// `Trace` implementation of a field. It must report the same `CcTrc`s every time it is called while no other code
+/// runs, and must not panic, create or drop `CcTrc`s. A `CcTrc` which is not reported is never part of a collected
+/// cycle, so leaving one out only leaks memory, but reporting one which is not owned by the value is unsound.
+///
+/// ## Examples
+/// ```
+/// use trc::{CcTrc, Trace, Tracer};
+///
+/// struct Pair {
+///     name: String,
+///     left: CcTrc<i32>,
+///     right: CcTrc<i32>,
+/// }
+///
+/// unsafe impl Trace for Pair {
+///     fn trace(&self, tracer: &mut Tracer<'_>) {
+///         self.left.trace(tracer);
+///         self.right.trace(tracer);
+///     }
+/// }
+/// ```
+pub unsafe trait Trace {
+    /// Pass every `CcTrc` owned by this value to `tracer`.
+    fn trace(&self, tracer: &mut Tracer<'_>);
+}
+
+/// Visitor which receives the `CcTrc`s reported by [`Trace::trace`]. It is created by the cycle collector.
+pub struct Tracer<'a> {
+    visit: &'a mut dyn FnMut(Node),
+}
+
+impl Debug for Tracer<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Tracer").finish_non_exhaustive()
+    }
+}
+
+/// `CcTrc<T>` is a reference counted pointer for values which may form reference cycles. Cycles which are no longer
+/// reachable from outside are reclaimed by [`collect_cycles`], so they do not need to be broken with [`Weak<T>`].
+/// The values must implement [`Trace`] to report the `CcTrc<T>`s they own.
+///
+/// `CcTrc<T>` does not implement `Send` or `Sync`, so a graph of `CcTrc<T>`s belongs to a single thread, and is
+/// collected by calling [`collect_cycles`] on it. The count is kept in the same shared state as for [`Trc<T>`](crate::Trc),
+/// but is never accessed by other threads.
+///
+/// A value is dropped when its count reaches zero, or when its cycle is collected. While the values of a cycle are
+/// dropped, dereferencing or cloning a `CcTrc<T>` to a value in the cycle panics.
+///
+/// To prevent name clashes, `CcTrc<T>`'s functions are associated.
+///
+/// ## Examples
+/// ```
+/// use std::cell::RefCell;
+/// use trc::{CcTrc, Trace, Tracer};
+///
+/// struct Observer {
+///     subject: RefCell<Option<CcTrc<Observer>>>,
+/// }
+///
+/// unsafe impl Trace for Observer {
+///     fn trace(&self, tracer: &mut Tracer<'_>) {
+///         self.subject.trace(tracer);
+///     }
+/// }
+///
+/// let a = CcTrc::new(Observer { subject: RefCell::new(None) });
+/// let b = CcTrc::new(Observer { subject: RefCell::new(Some(a.clone())) });
+/// *a.subject.borrow_mut() = Some(b.clone());
+/// assert_eq!(CcTrc::strong_count(&a), 2);
+///
+/// drop(b);
+/// drop(a);
+/// trc::collect_cycles();
+/// ```
+pub struct CcTrc<T: Trace + 'static> {
+    shared: NonNull<SharedTrcInternal<T>>,
+    phantom: PhantomData<SharedTrcInternal<T>>,
+}
+
+impl<T: Trace + 'static> CcTrc<T> {
+    /// Creates a new `CcTrc<T>` from the provided data.
+    ///
+    /// # Examples
+    /// ```
+    /// use trc::CcTrc;
+    ///
+    /// let cc = CcTrc::new(100);
+    /// assert_eq!(*cc, 100);
+    /// ```
+    #[inline]
+    pub fn new(value: T) -> Self {
+        let layout = Layout::new::<SharedTrcInternal<T>>();
+        let shared = allocate_for_layout(layout, &Global).cast::<SharedTrcInternal<T>>();
+
+        unsafe {
+            write(
+                shared.as_ptr(),
+                SharedTrcInternal {
+                    atomicref: AtomicUsize::new(1),
+                    weakcount: AtomicUsize::new(1),
+                    threadref: UnsafeCell::new(BLACK),
+                    #[cfg(feature = "reclaim")]
+                    reclaim: None,
+                    #[cfg(feature = "home")]
+                    home: None,
+                    data: value,
+                },
+            )
+        };
+
+        CcTrc {
+            shared,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Return the number of `CcTrc<T>`s pointing to the value.
+    ///
+    /// # Examples
+    /// ```
+    /// use trc::CcTrc;
+    ///
+    /// let cc = CcTrc::new(100);
+    /// let cc2 = cc.clone();
+    /// assert_eq!(CcTrc::strong_count(&cc), 2);
+    /// ```
+    #[inline]
+    pub fn strong_count(this: &Self) -> usize {
+        unsafe { strong(this.node()) }.load(Ordering::Relaxed)
+    }
+
+    /// Checks if the two `CcTrc<T>`s point to the same allocation.
+    ///
+    /// # Examples
+    /// ```
+    /// use trc::CcTrc;
+    ///
+    /// let cc1 = CcTrc::new(100);
+    /// let cc2 = cc1.clone();
+    /// assert!(CcTrc::ptr_eq(&cc1, &cc2));
+    /// ```
+    #[inline]
+    pub fn ptr_eq(this: &Self, other: &Self) -> bool {
+        this.shared.as_ptr() == other.shared.as_ptr()
+    }
+
+    #[inline]
+    fn node(&self) -> Node {
+        self.shared
+    }
+
+    /// Panic if the value has been dropped by the cycle collector.
+    #[inline]
+    fn assert_live(&self) {
+        assert!(
+            unsafe { *flags(self.node()) } & DROPPED == 0,
+            "CcTrc used while its cycle is being collected."
+        );
+    }
+}
+
+impl<T: Trace + 'static> Clone for CcTrc<T> {
+    /// Clone a `CcTrc<T>`, incrementing the count. Panics if the value has been dropped by the cycle collector.
+    ///
+    /// # Examples
+    /// ```
+    /// use trc::CcTrc;
+    ///
+    /// let cc = CcTrc::new(100);
+    /// let cc2 = cc.clone();
+    /// assert!(CcTrc::ptr_eq(&cc, &cc2));
+    /// ```
+    #[inline]
+    fn clone(&self) -> Self {
+        self.assert_live();
+
+        let node = self.node();
+        let count = unsafe { strong(node) };
+        let prev = count.load(Ordering::Relaxed);
+        if prev >= MAX_REFCOUNT {
+            panic!("Overflow of maximum strong reference count.");
+        }
+        count.store(prev + 1, Ordering::Relaxed);
+        unsafe { set_color(node, BLACK) };
+
+        CcTrc {
+            shared: self.shared,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<T: Trace + 'static> Deref for CcTrc<T> {
+    type Target = T;
+
+    /// Get an immutable reference to the internal data. Panics if the value has been dropped by the cycle collector.
+    ///
+    /// # Examples
+    /// ```
+    /// use trc::CcTrc;
+    ///
+    /// let cc = CcTrc::new(100i32);
+    /// assert_eq!(*cc, 100i32);
+    /// ```
+    #[inline]
+    fn deref(&self) -> &T {
+        self.assert_live();
+        &unsafe { self.shared.as_ref() }.data
+    }
+}
+
+impl<T: Trace + 'static> Drop for CcTrc<T> {
+    #[inline]
+    fn drop(&mut self) {
+        let node = self.node();
+        let count = unsafe { strong(node) };
+        let prev = count.load(Ordering::Relaxed);
+        count.store(prev - 1, Ordering::Relaxed);
+
+        if prev == 1 {
+            unsafe { release(node) };
+        } else {
+            unsafe { possible_root(node) };
+        }
+    }
+}
+
+impl<T: Trace + 'static> AsRef<T> for CcTrc<T> {
+    fn as_ref(&self) -> &T {
+        self
+    }
+}
+
+impl<T: Trace + Display + 'static> Display for CcTrc<T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        Display::fmt(&**self, f)
+    }
+}
+
+impl<T: Trace + Debug + 'static> Debug for CcTrc<T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        Debug::fmt(&**self, f)
+    }
+}
+
+unsafe impl<T: Trace + 'static> Trace for CcTrc<T> {
+    #[inline]
+    fn trace(&self, tracer: &mut Tracer<'_>) {
+        (tracer.visit)(self.node());
+    }
+}
+
+#[inline]
+unsafe fn strong<'a>(node: Node) -> &'a AtomicUsize {
+    &(*node.as_ptr()).atomicref
+}
+
+#[inline]
+unsafe fn flags(node: Node) -> *mut usize {
+    (*node.as_ptr()).threadref.get()
+}
+
+#[inline]
+unsafe fn color(node: Node) -> usize {
+    *flags(node) & COLOR
+}
+
+#[inline]
+unsafe fn set_color(node: Node, color: usize) {
+    *flags(node) = (*flags(node) & !COLOR) | color;
+}
+
+#[inline]
+unsafe fn add_strong(node: Node, offset: usize) {
+    let count = strong(node);
+    count.store(count.load(Ordering::Relaxed) + offset, Ordering::Relaxed);
+}
+
+#[inline]
+unsafe fn sub_strong(node: Node, offset: usize) -> usize {
+    let count = strong(node);
+    let new = count.load(Ordering::Relaxed) - offset;
+    count.store(new, Ordering::Relaxed);
+    new
+}
+
+/// Call `visit` for every `CcTrc` owned by the value. A dropped value owns none.
+#[inline]
+unsafe fn children(node: Node, mut visit: impl FnMut(Node)) {
+    if *flags(node) & DROPPED != 0 {
+        return;
+    }
+    (*node.as_ptr())
+        .data
+        .trace(&mut Tracer { visit: &mut visit });
+}
+
+/// Drop the value, unless it was already dropped by the cycle collector, and release the implicit weak reference.
+unsafe fn release(node: Node) {
+    let flags = flags(node);
+    let dropped = *flags & DROPPED != 0;
+    *flags = (*flags & BUFFERED) | DROPPED | BLACK;
+
+    if !dropped {
+        ptr::drop_in_place(addr_of_mut!((*node.as_ptr()).data));
+    }
+    drop(Weak {
+        data: node,
+        alloc: Global,
+    });
+}
+
+/// Mark the allocation as a possible root of a garbage cycle, and add it to this thread's buffer.
+unsafe fn possible_root(node: Node) {
+    let state = *flags(node);
+    if state & COLOR == PURPLE || state & DROPPED != 0 {
+        return;
+    }
+    set_color(node, PURPLE);
+
+    if state & BUFFERED == 0 {
+        //The buffer is gone if the thread is exiting, in which case the allocation is not tracked
+        let buffered = ROOTS.try_with(|roots| roots.borrow_mut().0.push(node));
+        if buffered.is_ok() {
+            *flags(node) |= BUFFERED;
+            add_weak(node);
+        }
+    }
+}
+
+#[inline]
+unsafe fn add_weak(node: Node) {
+    let weak = &(*node.as_ptr()).weakcount;
+    weak.store(weak.load(Ordering::Relaxed) + 1, Ordering::Relaxed);
+}
+
+/// Remove the allocation from the buffer, releasing the buffer's weak reference.
+#[inline]
+unsafe fn unbuffer(node: Node) {
+    *flags(node) &= !BUFFERED;
+    drop(Weak {
+        data: node,
+        alloc: Global,
+    });
+}
+
+/// Resets the guard against reentrant collections, even if a destructor panics.
+struct CollectingGuard;
+
+impl Drop for CollectingGuard {
+    fn drop(&mut self) {
+        COLLECTING.with(|collecting| collecting.set(false));
+    }
+}
+
+/// Reclaim the cycles of `CcTrc`s on the current thread which are no longer reachable from outside the cycle.
+/// Calls made while the values of a cycle are dropped return without collecting.
+///
+/// # Examples
+/// ```
+/// use std::cell::RefCell;
+/// use trc::{CcTrc, Trace, Tracer};
+///
+/// struct Node(RefCell<Option<CcTrc<Node>>>);
+///
+/// unsafe impl Trace for Node {
+///     fn trace(&self, tracer: &mut Tracer<'_>) {
+///         self.0.trace(tracer);
+///     }
+/// }
+///
+/// let node = CcTrc::new(Node(RefCell::new(None)));
+/// *node.0.borrow_mut() = Some(node.clone());
+/// drop(node);
+///
+/// //The node refers to itself, so it is only dropped here
+/// trc::collect_cycles();
+/// ```
+pub fn collect_cycles() {
+    if COLLECTING.with(|collecting| collecting.replace(true)) {
+        return;
+    }
+    let _guard = CollectingGuard;
+
+    let roots = ROOTS.with(|roots| mem::take(&mut roots.borrow_mut().0));
+
+    unsafe {
+        let mut candidates = Vec::new();
+        for node in roots {
+            if color(node) == PURPLE {
+                mark_gray(node);
+                candidates.push(node);
+            } else {
+                unbuffer(node);
+            }
+        }
+
+        for &node in &candidates {
+            scan(node);
+        }
+
+        //The candidates keep their allocations alive until the garbage is freed
+        for &node in &candidates {
+            *flags(node) &= !BUFFERED;
+        }
+        let mut garbage = Vec::new();
+        for &node in &candidates {
+            collect_white(node, &mut garbage);
+        }
+
+        free(&garbage);
+
+        for node in candidates {
+            drop(Weak {
+                data: node,
+                alloc: Global,
+            });
+        }
+    }
+}
+
+/// Subtract the references held by the values reachable from `root`, marking them gray.
+unsafe fn mark_gray(root: Node) {
+    if color(root) == GRAY {
+        return;
+    }
+    set_color(root, GRAY);
+
+    let mut stack = vec![root];
+    while let Some(node) = stack.pop() {
+        children(node, |child| {
+            sub_strong(child, 1);
+            if color(child) != GRAY {
+                set_color(child, GRAY);
+                stack.push(child);
+            }
+        });
+    }
+}
+
+/// Mark the gray values reachable from `root` white if their count is zero, or restore the values reachable from a
+/// value which is still referenced from outside.
+unsafe fn scan(root: Node) {
+    let mut stack = vec![root];
+    while let Some(node) = stack.pop() {
+        if color(node) != GRAY {
+            continue;
+        }
+
+        if strong(node).load(Ordering::Relaxed) > 0 {
+            scan_black(node);
+        } else {
+            set_color(node, WHITE);
+            children(node, |child| stack.push(child));
+        }
+    }
+}
+
+/// Restore the references held by the values reachable from `root`, marking them black.
+unsafe fn scan_black(root: Node) {
+    set_color(root, BLACK);
+
+    let mut stack = vec![root];
+    while let Some(node) = stack.pop() {
+        children(node, |child| {
+            add_strong(child, 1);
+            if color(child) != BLACK {
+                set_color(child, BLACK);
+                stack.push(child);
+            }
+        });
+    }
+}
+
+/// Add the white values reachable from `root` to `garbage`.
+unsafe fn collect_white(root: Node, garbage: &mut Vec<Node>) {
+    let mut stack = vec![root];
+    while let Some(node) = stack.pop() {
+        if color(node) != WHITE {
+            continue;
+        }
+        set_color(node, BLACK);
+        garbage.push(node);
+        children(node, |child| stack.push(child));
+    }
+}
+
+/// Drop the values of a garbage cycle, and free the allocations which were not resurrected by a destructor.
+unsafe fn free(garbage: &[Node]) {
+    //Restore the references held by the garbage, which are released as the values are dropped
+    for &node in garbage {
+        children(node, |child| add_strong(child, 1));
+    }
+
+    //Hold a reference to each allocation so that dropping one value never frees another one
+    let mut dropped = Vec::with_capacity(garbage.len());
+    for &node in garbage {
+        dropped.push(*flags(node) & DROPPED != 0);
+        *flags(node) |= DROPPED;
+        add_strong(node, 1);
+    }
+
+    for (&node, dropped) in garbage.iter().zip(dropped) {
+        if !dropped {
+            ptr::drop_in_place(addr_of_mut!((*node.as_ptr()).data));
+        }
+    }
+
+    //A destructor may have stored a `CcTrc` to the garbage, which then frees the allocation when it is dropped
+    for &node in garbage {
+        if sub_strong(node, 1) == 0 {
+            release(node);
+        }
+    }
+}
+
+macro_rules! trace_leaf {
+    ($($ty:ty),* $(,)?) => {
+        $(
+            unsafe impl Trace for $ty {
+                #[inline]
+                fn trace(&self, _tracer: &mut Tracer<'_>) {}
+            }
+        )*
+    };
+}
+
+trace_leaf!(
+    (),
+    bool,
+    char,
+    u8,
+    u16,
+    u32,
+    u64,
+    u128,
+    usize,
+    i8,
+    i16,
+    i32,
+    i64,
+    i128,
+    isize,
+    f32,
+    f64,
+    str,
+    String,
+);
+
+unsafe impl<T: ?Sized> Trace for PhantomData<T> {
+    #[inline]
+    fn trace(&self, _tracer: &mut Tracer<'_>) {}
+}
+
+//A `Copy` value cannot own a `CcTrc`
+unsafe impl<T: Copy> Trace for Cell<T> {
+    #[inline]
+    fn trace(&self, _tracer: &mut Tracer<'_>) {}
+}
+
+unsafe impl<T: ?Sized + Trace> Trace for RefCell<T> {
+    /// Trace the value, unless it is mutably borrowed. The borrow cannot change while the collector runs, so the
+    /// same `CcTrc`s are reported every time.
+    #[inline]
+    fn trace(&self, tracer: &mut Tracer<'_>) {
+        if let Ok(value) = self.try_borrow() {
+            value.trace(tracer);
+        }
+    }
+}
+
+unsafe impl<T: ?Sized + Trace> Trace for Box<T> {
+    #[inline]
+    fn trace(&self, tracer: &mut Tracer<'_>) {
+        (**self).trace(tracer);
+    }
+}
+
+unsafe impl<T: Trace> Trace for Option<T> {
+    #[inline]
+    fn trace(&self, tracer: &mut Tracer<'_>) {
+        if let Some(value) = self {
+            value.trace(tracer);
+        }
+    }
+}
+
+unsafe impl<T: Trace> Trace for [T] {
+    #[inline]
+    fn trace(&self, tracer: &mut Tracer<'_>) {
+        for value in self {
+            value.trace(tracer);
+        }
+    }
+}
+
+unsafe impl<T: Trace, const N: usize> Trace for [T; N] {
+    #[inline]
+    fn trace(&self, tracer: &mut Tracer<'_>) {
+        self.as_slice().trace(tracer);
+    }
+}
+
+unsafe impl<T: Trace> Trace for Vec<T> {
+    #[inline]
+    fn trace(&self, tracer: &mut Tracer<'_>) {
+        self.as_slice().trace(tracer);
+    }
+}
+
+unsafe impl<T: Trace> Trace for VecDeque<T> {
+    #[inline]
+    fn trace(&self, tracer: &mut Tracer<'_>) {
+        for value in self {
+            value.trace(tracer);
+        }
+    }
+}
+
+macro_rules! trace_tuple {
+    ($($name:ident)+) => {
+        unsafe impl<$($name: Trace),+> Trace for ($($name,)+) {
+            #[inline]
+            #[allow(non_snake_case)]
+            fn trace(&self, tracer: &mut Tracer<'_>) {
+                let ($($name,)+) = self;
+                $($name.trace(tracer);)+
+            }
+        }
+    };
+}
+
+trace_tuple!(A);
+trace_tuple!(A B);
+trace_tuple!(A B C);
+trace_tuple!(A B C D);
+trace_tuple!(A B C D E);
+trace_tuple!(A B C D E F);
@@ -0,0 +1,120 @@
+//! `HeaderSlice<H, [T]>`, a header and a slice which are stored in the same `Trc` allocation.
+
+use std::{
+    alloc::{Layout, LayoutError},
+    ptr::{self, addr_of_mut, slice_from_raw_parts_mut, write, NonNull},
+};
+
+use allocator_api2::alloc::{Allocator, Global};
+
+use crate::{
+    allocate_for_layout, inner_layout, write_counts, SharedTrcInternal, SliceBuilder, SliceData,
+    Trc,
+};
+
+/// A header `H` followed by a slice `S`, which is usually `[T]`. A `Trc<HeaderSlice<H, [T]>>` stores both in a
+/// single allocation, and is created with [`Trc::from_header_and_iter`]. Both parts are accessed through [`Deref`](std::ops::Deref).
+///
+/// ## Examples
+/// ```
+/// use trc::{HeaderSlice, Trc};
+///
+/// let node: Trc<HeaderSlice<&str, [u32]>> = Trc::from_header_and_iter("add", vec![1, 2]);
+/// assert_eq!(node.header, "add");
+/// assert_eq!(node.slice, [1, 2]);
+/// ```
+#[repr(C)]
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct HeaderSlice<H, S: ?Sized> {
+    /// The header.
+    pub header: H,
+    /// The slice, which is stored after the header.
+    pub slice: S,
+}
+
+impl<H, T> SliceData for HeaderSlice<H, [T]> {
+    type Elem = T;
+
+    #[inline]
+    unsafe fn elems(ptr: *mut Self) -> *mut T {
+        addr_of_mut!((*ptr).slice) as *mut T
+    }
+
+    #[inline]
+    unsafe fn drop_header(ptr: *mut Self) {
+        ptr::drop_in_place(addr_of_mut!((*ptr).header));
+    }
+}
+
+/// Layout of a `SharedTrcInternal<HeaderSlice<H, [T]>>` holding `len` elements.
+#[inline]
+fn header_slice_layout<H, T>(len: usize) -> Result<Layout, LayoutError> {
+    let data = Layout::new::<H>()
+        .extend(Layout::array::<T>(len)?)?
+        .0
+        .pad_to_align();
+    Ok(inner_layout(data)?.0)
+}
+
+impl<H, T> Trc<HeaderSlice<H, [T]>> {
+    /// Constructs a new `Trc<HeaderSlice<H, [T]>>` from a header and the elements of an iterator with an exact size,
+    /// in a single allocation. Panics if the iterator yields a different number of elements than its `len`.
+    ///
+    /// # Examples
+    /// ```
+    /// use trc::{HeaderSlice, Trc};
+    ///
+    /// let trc = Trc::from_header_and_iter(String::from("children"), (1..4).map(|x| x * 10));
+    /// assert_eq!(trc.header, "children");
+    /// assert_eq!(trc.slice, [10, 20, 30]);
+    /// ```
+    pub fn from_header_and_iter<I>(header: H, iter: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        I::IntoIter: ExactSizeIterator,
+    {
+        Self::from_header_and_iter_in(header, iter, Global)
+    }
+}
+
+impl<H, T, A: Allocator> Trc<HeaderSlice<H, [T]>, A> {
+    /// Constructs a new `Trc<HeaderSlice<H, [T]>, A>` from a header and the elements of an iterator with an exact
+    /// size, in a single allocation in the provided allocator. Panics if the iterator yields a different number of
+    /// elements than its `len`.
+    ///
+    /// # Examples
+    /// ```
+    /// # #![cfg_attr(feature = "nightly", feature(allocator_api))]
+    /// use trc::{HeaderSlice, Trc};
+    /// use allocator_api2::alloc::Global;
+    ///
+    /// let trc = Trc::from_header_and_iter_in(1, [2, 3], Global);
+    /// assert_eq!(trc.header, 1);
+    /// assert_eq!(trc.slice, [2, 3]);
+    /// ```
+    pub fn from_header_and_iter_in<I>(header: H, iter: I, alloc: A) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        I::IntoIter: ExactSizeIterator,
+    {
+        let iterator = iter.into_iter();
+        let len = iterator.len();
+
+        let layout = header_slice_layout::<H, T>(len).expect("Overflow of maximum slice length.");
+        let ptr = allocate_for_layout(layout, &alloc);
+        let shared = unsafe {
+            let res = slice_from_raw_parts_mut(ptr.as_ptr() as *mut T, len)
+                as *mut SharedTrcInternal<HeaderSlice<H, [T]>>;
+            write_counts(res, 1);
+            write(addr_of_mut!((*res).data.header), header);
+            NonNull::new_unchecked(res)
+        };
+
+        let mut builder = unsafe { SliceBuilder::from_allocation(shared, layout, len, &alloc) };
+        builder.extend(iterator);
+        let shared = builder.finish();
+        debug_assert_eq!(Layout::for_value(unsafe { shared.as_ref() }), layout);
+
+        unsafe { Trc::from_inner_inline(shared, alloc) }
+    }
+}
@@ -0,0 +1,289 @@
+//! `DropHome`, a queue of values whose destructors run on a designated thread, enabled by the `home` feature.
+//!
+//! An allocation created with [`Trc::new_with_drop_home`] records its home in its header. When the last strong
+//! reference to it is released on another thread, from a `Trc<T>` or `SharedTrc<T>`, its value is added to the queue
+//! of the home instead of being dropped, and the allocation is freed after the value is dropped on the home thread.
+
+use std::{
+    fmt::Debug,
+    mem,
+    ptr::{addr_of, addr_of_mut, NonNull},
+    rc::Rc,
+    sync::{Arc, Mutex, MutexGuard},
+    thread::{self, ThreadId},
+};
+
+use allocator_api2::alloc::Global;
+
+use crate::{SharedTrcInternal, Trc, Weak};
+
+thread_local! {
+    /// The id of the current thread, which is cheaper to compare than getting `thread::current()` on every release.
+    static THREAD: ThreadId = thread::current().id();
+}
+
+struct Home {
+    thread: ThreadId,
+    state: Mutex<State>,
+}
+
+struct State {
+    queue: Vec<Queued>,
+    /// Whether a `DropHome` still refers to the home, so that the queue will be collected.
+    open: bool,
+}
+
+impl Home {
+    /// Check if the current thread is the home thread.
+    #[inline]
+    fn is_home(&self) -> bool {
+        THREAD
+            .try_with(|thread| *thread == self.thread)
+            .unwrap_or(false)
+    }
+
+    /// Lock the state. A panic while it is locked cannot leave it in an inconsistent state, so poisoning is ignored.
+    #[inline]
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// The value of an allocation waiting in the queue of a `DropHome`, which is dropped with it.
+struct Queued {
+    shared: NonNull<u8>,
+    drop: unsafe fn(NonNull<u8>),
+}
+
+//The value is `Send`, and the queue holds its last strong reference
+unsafe impl Send for Queued {}
+
+impl Drop for Queued {
+    #[inline]
+    fn drop(&mut self) {
+        unsafe { (self.drop)(self.shared) };
+    }
+}
+
+/// How the value of an allocation created by `Trc::new_with_drop_home` is released. It is stored in the header of the
+/// allocation, which is `None` for other allocations, and holds a strong reference to the home until the allocation
+/// is freed.
+pub(crate) struct HomeRelease {
+    home: NonNull<Home>,
+    /// Drop the value and release the implicit weak reference, from the thin pointer to the allocation.
+    drop: unsafe fn(NonNull<u8>),
+}
+
+/// Add the value of an allocation created by `Trc::new_with_drop_home` to the queue of its home, if the current thread
+/// is not the home thread and a `DropHome` still refers to it. Returns `false` if the caller must drop the value.
+///
+/// # Safety
+/// `shared` must be the last strong reference to the allocation, and must not be used afterwards if `true` is returned.
+#[inline]
+pub(crate) unsafe fn release<T: ?Sized>(shared: NonNull<SharedTrcInternal<T>>) -> bool {
+    let Some(release) = &*addr_of!((*shared.as_ptr()).home) else {
+        return false;
+    };
+
+    let home = release.home.as_ref();
+    if home.is_home() {
+        return false;
+    }
+
+    let mut state = home.lock();
+    if !state.open {
+        //Nothing will collect the queue anymore, so the value is dropped here instead of being leaked
+        return false;
+    }
+    state.queue.push(Queued {
+        shared: shared.cast(),
+        drop: release.drop,
+    });
+    true
+}
+
+/// Release the reference to the home of an allocation created by `Trc::new_with_drop_home`, before it is freed.
+///
+/// # Safety
+/// `shared` must be the last weak reference to the allocation.
+#[inline]
+pub(crate) unsafe fn release_allocation<T: ?Sized>(shared: NonNull<SharedTrcInternal<T>>) {
+    if let Some(release) = (*addr_of_mut!((*shared.as_ptr()).home)).take() {
+        drop(Arc::from_raw(release.home.as_ptr()));
+    }
+}
+
+/// `HomeRelease::drop` for a `SharedTrcInternal<T>` allocated with `Global`.
+unsafe fn drop_value<T>(shared: NonNull<u8>) {
+    let shared = shared.cast::<SharedTrcInternal<T>>();
+    std::ptr::drop_in_place(addr_of_mut!((*shared.as_ptr()).data));
+    drop(Weak {
+        data: shared,
+        alloc: Global,
+    });
+}
+
+/// The last `DropHome` referring to a home, which closes it when it is dropped.
+struct Handle(Arc<Home>);
+
+impl Drop for Handle {
+    fn drop(&mut self) {
+        //The queue is dropped after the lock is released, since destructors can release other values
+        let queue = {
+            let mut state = self.0.lock();
+            state.open = false;
+            mem::take(&mut state.queue)
+        };
+        drop(queue);
+    }
+}
+
+/// `DropHome` is a queue of values which are dropped on its home thread, which is the thread that created it.
+/// A `Trc<T>` created with [`Trc::new_with_drop_home`] adds its value to the queue if its last strong reference is
+/// released on another thread. The queue is drained by [`DropHome::collect`]. If the last reference is released on
+/// the home thread, the value is dropped immediately.
+///
+/// This moves the destructors of values that must be destroyed on a specific thread, or whose destructors are
+/// expensive, off the thread which happened to release the last reference.
+///
+/// `DropHome` is a handle to a shared queue, so clones refer to the same queue. It is not [`Send`] or [`Sync`], so it
+/// never leaves the home thread. When the last handle is dropped, the values still in the queue are dropped with it.
+/// Afterwards, nothing collects the queue anymore, so values are dropped on the thread which releases them, like any
+/// other `Trc<T>`.
+///
+/// ## Examples
+/// ```
+/// use std::thread;
+/// use trc::{DropHome, SharedTrc, Trc};
+///
+/// let home = DropHome::new();
+/// let trc = Trc::new_with_drop_home(vec![1, 2, 3], home.clone());
+/// let shared = SharedTrc::from(trc);
+///
+/// thread::spawn(move || {
+///     let trc = SharedTrc::to_trc(shared);
+///     assert_eq!(trc.len(), 3);
+/// })
+/// .join()
+/// .unwrap();
+///
+/// //The vector was released on the other thread, but is dropped here
+/// assert_eq!(home.pending(), 1);
+/// home.collect();
+/// assert_eq!(home.pending(), 0);
+/// ```
+#[derive(Clone)]
+pub struct DropHome {
+    handle: Rc<Handle>,
+}
+
+impl DropHome {
+    /// Create a new `DropHome` whose home thread is the current thread.
+    ///
+    /// # Examples
+    /// ```
+    /// use trc::DropHome;
+    ///
+    /// let home = DropHome::new();
+    /// assert_eq!(home.pending(), 0);
+    /// ```
+    #[inline]
+    pub fn new() -> Self {
+        DropHome {
+            handle: Rc::new(Handle(Arc::new(Home {
+                thread: thread::current().id(),
+                state: Mutex::new(State {
+                    queue: Vec::new(),
+                    open: true,
+                }),
+            }))),
+        }
+    }
+
+    /// Drop all values in the queue.
+    ///
+    /// # Examples
+    /// ```
+    /// use std::thread;
+    /// use trc::{DropHome, SharedTrc, Trc};
+    ///
+    /// let home = DropHome::new();
+    /// let shared = SharedTrc::from(Trc::new_with_drop_home(String::from("value"), home.clone()));
+    /// thread::spawn(move || drop(shared)).join().unwrap();
+    /// assert_eq!(home.pending(), 1);
+    ///
+    /// home.collect();
+    /// assert_eq!(home.pending(), 0);
+    /// ```
+    pub fn collect(&self) {
+        //The lock is released before dropping, since destructors can release other values
+        let queue = mem::take(&mut self.handle.0.lock().queue);
+        drop(queue);
+    }
+
+    /// Return the number of values waiting to be dropped.
+    ///
+    /// # Examples
+    /// ```
+    /// use trc::{DropHome, Trc};
+    ///
+    /// let home = DropHome::new();
+    /// let trc = Trc::new_with_drop_home(100, home.clone());
+    /// assert_eq!(home.pending(), 0);
+    /// drop(trc);
+    /// assert_eq!(home.pending(), 0);
+    /// ```
+    #[inline]
+    pub fn pending(&self) -> usize {
+        self.handle.0.lock().queue.len()
+    }
+}
+
+impl Default for DropHome {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Debug for DropHome {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("DropHome")
+            .field("thread", &self.handle.0.thread)
+            .field("pending", &self.pending())
+            .finish()
+    }
+}
+
+impl<T: Send + 'static> Trc<T> {
+    /// Creates a new `Trc<T>` from the provided data. When the last strong reference is released on another thread,
+    /// from a `Trc<T>` or `SharedTrc<T>`, the data is not dropped there, but is added to the queue of `home` and
+    /// dropped by [`DropHome::collect`] on its home thread.
+    ///
+    /// # Examples
+    /// ```
+    /// use trc::{DropHome, Trc};
+    ///
+    /// let home = DropHome::new();
+    /// let trc = Trc::new_with_drop_home(String::from("value"), home.clone());
+    /// assert_eq!(trc.as_str(), "value");
+    ///
+    /// //The last reference is released on the home thread, so the value is dropped immediately
+    /// drop(trc);
+    /// assert_eq!(home.pending(), 0);
+    /// ```
+    #[inline]
+    pub fn new_with_drop_home(value: T, home: DropHome) -> Self {
+        let trc = Trc::new(value);
+        let home = Arc::into_raw(home.handle.0.clone()) as *mut Home;
+        unsafe {
+            *addr_of_mut!((*trc.shared.as_ptr()).home) = Some(HomeRelease {
+                home: NonNull::new_unchecked(home),
+                drop: drop_value::<T>,
+            })
+        };
+        trc
+    }
+}
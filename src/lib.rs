@@ -17,25 +17,138 @@
 //! However, `SharedTrc<T>` does, and it is the only way to safely send a `Trc<T>` across
 //! threads. See [`SharedTrc`] for it's API, which is similar to that of `Weak`.
 //! See [`SharedTrc`] for it's API, which is similar to that of [`Weak`].
+//!
+//! ## Cargo features
+//! - `registry`: Keep a per-thread registry of local thread reference counts, so that `SharedTrc::to_trc` and
+//!   `Weak::upgrade` reuse the local thread reference count of an existing `Trc<T>` on the same thread.
+//! - `pool`: Keep a per-thread pool of local thread reference counts, so that creating a `Trc<T>` from a `SharedTrc<T>`
+//!   or `Weak<T>` and dropping it again does not go through the global allocator. `Trc::new` does not allocate a local
+//!   thread reference count, because it is stored in the same allocation as the data.
+//! - `reclaim`: Add the `reclaim` module, whose `Trc::new_deferred` and `Trc::new_deferred_slice` create a `Trc`
+//!   whose value is dropped and whose allocation is freed on a background thread.
+//! - `home`: Add `DropHome`, whose `Trc::new_with_drop_home` creates a `Trc` whose value is dropped on a designated
+//!   thread.
+//! - `derive`: Implement [`Trace`] for structs and enums with `#[derive(Trace)]`.
+//! - `serde`: Implement `Serialize` and `Deserialize` for `Trc<T>` and `SharedTrc<T>`, which serialize as the value
+//!   they point to. `Trc<[T]>` and `Trc<str>` deserialize into a single allocation, and `Weak<T>` serializes as an
+//!   `Option<T>` of the upgraded value.
+//! - `nightly`: Use the unstable standard library `Allocator` trait instead of the one from `allocator-api2`, and
+//!   implement `CoerceUnsized` so that `Trc<T>`, `SharedTrc<T>` and `Weak<T>` coerce to unsized types implicitly.
+//!
+//! ## Trait objects
+//! On stable Rust, [`coerce_trc!`] converts a `Trc<T>`, `SharedTrc<T>` or `Weak<T>` into one pointing to an unsized
+//! type, such as a trait object or a slice, without another allocation:
+//! ```
+//! use trc::{coerce_trc, Trc};
+//!
+//! let display = coerce_trc!(Trc::new(100) => dyn std::fmt::Display);
+//! assert_eq!(display.to_string(), "100");
+//! ```
+//!
+//! ## Headers and slices
+//! [`Trc::from_header_and_iter`] stores a header and a slice in a single allocation, as a [`HeaderSlice<H, [T]>`]:
+//! ```
+//! use trc::{HeaderSlice, Trc};
+//!
+//! let node: Trc<HeaderSlice<&str, [u32]>> = Trc::from_header_and_iter("add", vec![1, 2]);
+//! assert_eq!(node.header, "add");
+//! assert_eq!(node.slice, [1, 2]);
+//! ```
+//!
+//! ## Thin pointers
+//! `Trc<[T]>` and `Trc<str>` hold a wide pointer, which stores the length next to the address. [`ThinTrc<H, [T]>`]
+//! and `ThinTrc<H, str>` store the length, and an optional header `H`, in the allocation instead, and use the local
+//! thread reference count stored there, which makes them a single word:
+//! ```
+//! use trc::ThinTrc;
+//!
+//! let thin = ThinTrc::from_header_and_iter("node", vec![1, 2, 3]);
+//! assert_eq!(*ThinTrc::header(&thin), "node");
+//! assert_eq!(*thin, [1, 2, 3]);
+//! ```
+//!
+//! ## Atomic cells
+//! [`AtomicTrc<T>`] and [`AtomicOptionTrc<T>`] hold a `SharedTrc<T>` which many threads can load and replace, such as
+//! a configuration which is reloaded at runtime. Loading returns a `Trc<T>` local to the loading thread, and an
+//! [`AtomicTrcCache<T>`] keeps it until the value is replaced, so that loading it again does not touch the reference
+//! counts.
+//!
+//! ## Dropping values on a designated thread
+//! With the `home` feature, `Trc::new_with_drop_home` creates a `Trc` whose value is dropped on the home thread of a
+//! `DropHome` by `DropHome::collect`, instead of on the thread which releases the last reference.
+//!
+//! ## Collecting cycles
+//! [`CcTrc<T>`] is a reference counted pointer for single threaded graphs with genuine cycles, such as observers and
+//! subjects. Its values implement [`Trace`], and [`collect_cycles`] reclaims the cycles on the current thread which
+//! are no longer reachable, without breaking them with `Weak<T>`.
+//!
+//! ## Custom allocators
+//! `Trc<T, A>`, `SharedTrc<T, A>` and `Weak<T, A>` take an optional allocator `A`, which defaults to [`Global`].
+//! The data and the counts shared between threads are allocated with `A`. Local thread reference counts for threads
+//! other than the one which created the allocation are always allocated with the global allocator.
+
+#![cfg_attr(feature = "nightly", feature(allocator_api, coerce_unsized, unsize))]
+
+#[cfg(feature = "derive")]
+pub use trc_derive::Trace;
+
+#[cfg(test)]
+extern crate self as trc;
 
 #[cfg(test)]
 mod tests;
 
+#[cfg(feature = "registry")]
+mod registry;
+
+#[cfg(feature = "pool")]
+mod pool;
+
+#[cfg(feature = "reclaim")]
+pub mod reclaim;
+
+#[cfg(feature = "serde")]
+mod serde;
+
+#[cfg(feature = "home")]
+mod home;
+
+mod atomic;
+mod cycle;
+mod header;
+mod thin;
+mod unique;
+
+pub use atomic::{AtomicOptionTrc, AtomicOptionTrcCache, AtomicTrc, AtomicTrcCache};
+pub use cycle::{collect_cycles, CcTrc, Trace, Tracer};
+pub use header::HeaderSlice;
+#[cfg(feature = "home")]
+pub use home::DropHome;
+pub use thin::ThinTrc;
+pub use unique::UniqueTrc;
+
 #[cfg(not(target_has_atomic = "ptr"))]
 compile_error!("Cannot use `Trc` on a system without atomics.");
 
+use allocator_api2::alloc::{handle_alloc_error, AllocError, Allocator, Global};
 use std::{
-    alloc::{alloc, Layout},
-    borrow::Borrow,
+    alloc::{Layout, LayoutError},
+    any::Any,
+    borrow::{Borrow, Cow},
+    cell::UnsafeCell,
     error::Error,
+    ffi::{CStr, CString, OsStr, OsString},
     fmt::{Debug, Display, Pointer},
     hash::{Hash, Hasher},
-    mem::{forget, ManuallyDrop, MaybeUninit},
+    mem::{ManuallyDrop, MaybeUninit},
     ops::Deref,
     os::fd::{AsFd, AsRawFd},
     panic::UnwindSafe,
+    path::{Path, PathBuf},
     pin::Pin,
-    ptr::{self, addr_of, addr_of_mut, slice_from_raw_parts_mut, write, NonNull}
+    ptr::{self, addr_of, addr_of_mut, slice_from_raw_parts_mut, write, NonNull},
+    str::Utf8Error,
+    sync::Arc,
 };
 
 use core::sync::atomic::AtomicUsize;
@@ -46,6 +159,16 @@ const MAX_REFCOUNT: usize = (isize::MAX) as usize;
 struct SharedTrcInternal<T: ?Sized> {
     atomicref: AtomicUsize,
     weakcount: AtomicUsize,
+    /// Local thread reference count of the thread that created the allocation. Storing it inline means that
+    /// constructing a `Trc<T>` only needs a single allocation. Other threads allocate their own local count.
+    threadref: UnsafeCell<usize>,
+    /// With the `reclaim` feature, how the allocation is released on the background thread if it was created by
+    /// `Trc::new_deferred`.
+    #[cfg(feature = "reclaim")]
+    reclaim: Option<reclaim::Deferral>,
+    /// With the `home` feature, the home whose thread drops the value if it was created by `Trc::new_with_drop_home`.
+    #[cfg(feature = "home")]
+    home: Option<home::HomeRelease>,
     data: T,
 }
 
@@ -90,10 +213,10 @@ struct SharedTrcInternal<T: ?Sized> {
 /// To prevent name clashes, `Trc<T>`'s functions are associated.
 ///
 /// ## Footnote on `dyn` wrapping
-/// Rust's limitations mean that `Trc` will not be able to be used as a method receiver wrapper until
-/// CoerceUnsized, and Receiver (with arbitrary_self_types) are stablized. However, DispatchFromDyn cannot be implemented due
-/// to the requirements of thread reference counting, and so `Trc` will not be able to be used as a trait object method receiver.
-/// As an alternative, one can use a [`Box`] as a wrapper and then wrap with `Trc<T>`.
+/// On stable Rust, a `Trc<T>` is converted into a `Trc<dyn Trait>` without another allocation by [`coerce_trc!`].
+/// With the `nightly` feature, `Trc<T>` implements `CoerceUnsized`, so the conversion is implicit. However,
+/// DispatchFromDyn cannot be implemented due to the requirements of thread reference counting, and so `Trc` will not
+/// be able to be used as a trait object method receiver.
 ///
 /// ## Examples
 ///
@@ -123,9 +246,10 @@ struct SharedTrcInternal<T: ?Sized> {
 /// assert_eq!(*trc, 100);
 /// ```
 ///
-pub struct Trc<T: ?Sized> {
+pub struct Trc<T: ?Sized, A: Allocator = Global> {
     shared: NonNull<SharedTrcInternal<T>>,
     threadref: NonNull<usize>,
+    alloc: A,
 }
 
 /// `SharedTrc<T>` is a thread-safe wrapper used to send `Trc<T>`s across threads.
@@ -148,8 +272,9 @@ pub struct Trc<T: ?Sized> {
 /// ```
 ///
 /// See [`Trc`] or [`Weak`] for an example with multiple threads.
-pub struct SharedTrc<T: ?Sized> {
+pub struct SharedTrc<T: ?Sized, A: Allocator = Global> {
     data: NonNull<SharedTrcInternal<T>>,
+    alloc: A,
 }
 
 /// `Weak<T>` is a non-owning reference to `Trc<T>`'s data. It is used to prevent cyclic references which cause memory to never be freed.
@@ -191,14 +316,15 @@ pub struct SharedTrc<T: ?Sized> {
 /// assert_eq!(*trc, 100);
 /// ```
 ///
-pub struct Weak<T: ?Sized> {
+pub struct Weak<T: ?Sized, A: Allocator = Global> {
     data: NonNull<SharedTrcInternal<T>>,
+    alloc: A,
 }
 
-unsafe impl<T: Sync + Send> Send for SharedTrc<T> {}
-unsafe impl<T: Sync + Send> Sync for SharedTrc<T> {}
+unsafe impl<T: ?Sized + Sync + Send, A: Allocator + Send> Send for SharedTrc<T, A> {}
+unsafe impl<T: ?Sized + Sync + Send, A: Allocator + Sync> Sync for SharedTrc<T, A> {}
 
-impl<T: ?Sized> SharedTrc<T> {
+impl<T: ?Sized, A: Allocator + Clone> SharedTrc<T, A> {
     /// Convert a `Trc<T>` to a `SharedTrc<T>`, incrementing it's atomic reference count.
     /// While this `SharedTrc<T>` is alive, the data contained by `Trc<T>` will not be dropped, which is
     /// unlike a `Weak<T>`.
@@ -212,7 +338,7 @@ impl<T: ?Sized> SharedTrc<T> {
     /// let shared = SharedTrc::from_trc(&trc);
     /// ```
     #[inline]
-    pub fn from_trc(trc: &Trc<T>) -> Self {
+    pub fn from_trc(trc: &Trc<T, A>) -> Self {
         let prev = sum_value(
             &unsafe { trc.shared.as_ref() }.atomicref,
             1,
@@ -221,13 +347,47 @@ impl<T: ?Sized> SharedTrc<T> {
         if prev > MAX_REFCOUNT {
             panic!("Overflow of maximum strong reference count.");
         }
-        SharedTrc { data: trc.shared }
+
+        #[cfg(feature = "registry")]
+        Trc::register(trc);
+
+        SharedTrc {
+            data: trc.shared,
+            alloc: trc.alloc.clone(),
+        }
+    }
+}
+
+impl<T: ?Sized, A: Allocator + Clone> SharedTrc<T, A> {
+    /// Create a `Weak<T>` from a `SharedTrc<T>`. This increments the weak count.
+    ///
+    /// # Examples
+    /// ```
+    /// use trc::Trc;
+    /// use trc::SharedTrc;
+    ///
+    /// let shared: SharedTrc<_> = Trc::new(100).into();
+    /// let weak = SharedTrc::downgrade(&shared);
+    /// assert_eq!(SharedTrc::weak_count(&shared), 2);
+    /// ```
+    #[inline]
+    pub fn downgrade(this: &Self) -> Weak<T, A> {
+        increment_weakcount(&unsafe { this.data.as_ref() }.weakcount);
+        Weak {
+            data: this.data,
+            alloc: this.alloc.clone(),
+        }
     }
+}
 
+impl<T: ?Sized, A: Allocator> SharedTrc<T, A> {
     /// Convert a `SharedTrc<T>` to a `Trc<T>`. To prevent memory leaks, this function takes
     /// ownership of the `SharedTrc`. Unlike `Weak::to_trc`, this function will not fail as it
     /// prevents the data from being dropped.
     ///
+    /// With the `registry` feature, if this thread already has a `Trc<T>` pointing to the same data, the returned
+    /// `Trc<T>` shares its local thread reference count and the atomic reference count is decremented.
+    ///
     /// # Examples
     /// ```
     /// use trc::Trc;
@@ -238,14 +398,10 @@ impl<T: ?Sized> SharedTrc<T> {
     /// drop(trc);
     /// let trc2 = SharedTrc::to_trc(shared);
     /// ```
-    pub fn to_trc(this: Self) -> Trc<T> {
-        let tbx = Box::new(1);
-        let res = Trc {
-            threadref: NonNull::from(Box::leak(tbx)),
-            shared: this.data,
-        };
-        core::mem::forget(this);
-        res
+    pub fn to_trc(this: Self) -> Trc<T, A> {
+        let this = ManuallyDrop::new(this);
+        let alloc = unsafe { ptr::read(&this.alloc) };
+        unsafe { Trc::from_shared(this.data, alloc) }
     }
 
     /// Return the atomic reference count of the object. This is how many threads are using the data referenced by this `SharedTrc<T>`.
@@ -275,7 +431,7 @@ impl<T: ?Sized> SharedTrc<T> {
     }
 }
 
-impl<T: ?Sized> Clone for SharedTrc<T> {
+impl<T: ?Sized, A: Allocator + Clone> Clone for SharedTrc<T, A> {
     /// Clone a `SharedTrc<T>` (increment the strong count).
     ///
     /// # Examples
@@ -298,11 +454,14 @@ impl<T: ?Sized> Clone for SharedTrc<T> {
         if prev > MAX_REFCOUNT {
             panic!("Overflow of maximum strong reference count.");
         }
-        SharedTrc { data: self.data }
+        SharedTrc {
+            data: self.data,
+            alloc: self.alloc.clone(),
+        }
     }
 }
 
-impl<T: ?Sized> Drop for SharedTrc<T> {
+impl<T: ?Sized, A: Allocator> Drop for SharedTrc<T, A> {
     #[inline]
     fn drop(&mut self) {
         if sub_value(
@@ -314,17 +473,25 @@ impl<T: ?Sized> Drop for SharedTrc<T> {
             return;
         }
 
-        let weak =
-            unsafe { &(*self.data.as_ptr()).weakcount }.load(core::sync::atomic::Ordering::Acquire);
-        if weak == 1 {
-            core::sync::atomic::fence(core::sync::atomic::Ordering::Acquire);
-            unsafe { core::ptr::drop_in_place(addr_of_mut!((*self.data.as_ptr()).data)) };
-            Weak { data: self.data };
+        core::sync::atomic::fence(core::sync::atomic::Ordering::Acquire);
+        #[cfg(feature = "reclaim")]
+        if unsafe { reclaim::release(self.data, reclaim::Part::Value) } {
+            return;
+        }
+        #[cfg(feature = "home")]
+        if unsafe { home::release(self.data) } {
+            return;
         }
+
+        unsafe { core::ptr::drop_in_place(addr_of_mut!((*self.data.as_ptr()).data)) };
+        drop(Weak {
+            data: self.data,
+            alloc: &self.alloc,
+        });
     }
 }
 
-impl<T: ?Sized> From<SharedTrc<T>> for Trc<T> {
+impl<T: ?Sized, A: Allocator> From<SharedTrc<T, A>> for Trc<T, A> {
     /// Convert a `SharedTrc<T>` to a `Trc<T>`. To prevent memory leaks, this function takes
     /// ownership of the `SharedTrc`. Unlike `Weak::to_trc`, this function will not fail as it
     /// prevents the data from being dropped.
@@ -339,12 +506,12 @@ impl<T: ?Sized> From<SharedTrc<T>> for Trc<T> {
     /// drop(trc);
     /// let trc2 = SharedTrc::to_trc(shared);
     /// ```
-    fn from(value: SharedTrc<T>) -> Self {
+    fn from(value: SharedTrc<T, A>) -> Self {
         SharedTrc::to_trc(value)
     }
 }
 
-impl<T: ?Sized> From<&Trc<T>> for SharedTrc<T> {
+impl<T: ?Sized, A: Allocator + Clone> From<&Trc<T, A>> for SharedTrc<T, A> {
     /// Convert a `Trc<T>` to a `SharedTrc<T>`, incrementing it's atomic reference count.
     /// While this `SharedTrc<T>` is alive, the data contained by `Trc<T>` will not be dropped, which is
     /// unlike a `Weak<T>`.
@@ -357,12 +524,12 @@ impl<T: ?Sized> From<&Trc<T>> for SharedTrc<T> {
     /// let trc = Trc::new(100);
     /// let shared = SharedTrc::from_trc(&trc);
     /// ```
-    fn from(value: &Trc<T>) -> Self {
+    fn from(value: &Trc<T, A>) -> Self {
         SharedTrc::from_trc(value)
     }
 }
 
-impl<T: ?Sized> From<Trc<T>> for SharedTrc<T> {
+impl<T: ?Sized, A: Allocator> From<Trc<T, A>> for SharedTrc<T, A> {
     /// Convert a `Trc<T>` to a `SharedTrc<T>`, incrementing it's atomic reference count.
     /// While this `SharedTrc<T>` is alive, the data contained by `Trc<T>` will not be dropped, which is
     /// unlike a `Weak<T>`.
@@ -375,12 +542,13 @@ impl<T: ?Sized> From<Trc<T>> for SharedTrc<T> {
     /// let trc = Trc::new(100);
     /// let shared = SharedTrc::from_trc(&trc);
     /// ```
-    fn from(value: Trc<T>) -> Self {
-        SharedTrc::from_trc(&value)
+    fn from(value: Trc<T, A>) -> Self {
+        let (data, alloc) = Trc::into_shared_parts(value);
+        SharedTrc { data, alloc }
     }
 }
 
-impl<T: ?Sized> SharedTrc<T> {
+impl<T: ?Sized, A: Allocator> SharedTrc<T, A> {
     /// Return the weak count of the object. This is how many weak counts - across all threads - are pointing to the allocation inside of `SharedTrc<T>`.
     /// It includes the implicit weak reference held by all `SharedTrc<T>` to themselves.
     ///
@@ -420,7 +588,7 @@ impl<T: ?Sized> SharedTrc<T> {
     /// ```
     #[inline]
     pub fn ptr_eq(this: &Self, other: &Self) -> bool {
-        this.data.as_ptr() == other.data.as_ptr()
+        this.data.cast::<u8>() == other.data.cast::<u8>()
     }
 
     /// Gets the raw pointer to the most inner layer of `SharedTrc<T>`.
@@ -455,14 +623,52 @@ impl<T: ?Sized> SharedTrc<T> {
     /// unsafe { Trc::from_raw(ptr) };
     /// ```
     pub fn into_raw(this: Self) -> *const T {
+        let (ptr, alloc) = Self::into_raw_with_allocator(this);
+        drop(alloc);
+        ptr
+    }
+
+    /// Converts a `SharedTrc<T, A>` into `*const T`, without freeing the allocation, and returns the allocator.
+    /// To avoid a memory leak, be sure to call `from_raw_in` to reclaim the allocation.
+    ///
+    /// # Examples
+    /// ```
+    /// use trc::Trc;
+    /// use trc::SharedTrc;
+    ///
+    /// let shared: SharedTrc<_> = Trc::new(100).into();
+    /// let (ptr, alloc) = SharedTrc::into_raw_with_allocator(shared);
+    ///
+    /// assert_eq!(unsafe { *ptr }, 100);
+    ///
+    /// unsafe { SharedTrc::from_raw_in(ptr, alloc) };
+    /// ```
+    pub fn into_raw_with_allocator(this: Self) -> (*const T, A) {
         let ptr = Self::as_ptr(&this);
+        let this = ManuallyDrop::new(this);
+        let alloc = unsafe { ptr::read(&this.alloc) };
+        (ptr, alloc)
+    }
 
-        forget(this);
-        ptr
+    /// Returns a reference to the underlying allocator.
+    ///
+    /// # Examples
+    /// ```
+    /// # #![cfg_attr(feature = "nightly", feature(allocator_api))]
+    /// use trc::Trc;
+    /// use trc::SharedTrc;
+    /// use allocator_api2::alloc::Global;
+    ///
+    /// let shared: SharedTrc<_> = Trc::new(100).into();
+    /// let _: &Global = SharedTrc::allocator(&shared);
+    /// ```
+    #[inline]
+    pub fn allocator(this: &Self) -> &A {
+        &this.alloc
     }
 }
 
-impl<T> SharedTrc<T> {
+impl<T: ?Sized> SharedTrc<T> {
     /// Converts a `*const T` into `SharedTrc<T>`. The caller must uphold the below safety constraints.
     /// To avoid a memory leak, be sure to call `from_raw` to reclaim the allocation.
     ///
@@ -483,18 +689,270 @@ impl<T> SharedTrc<T> {
     /// unsafe { Trc::from_raw(ptr) };
     /// ```
     pub unsafe fn from_raw(ptr: *const T) -> Self {
-        let layout = Layout::new::<SharedTrcInternal<()>>();
-        let n = layout.size();
+        Self::from_raw_in(ptr, Global)
+    }
+}
 
-        let data_ptr = (ptr as *const u8).sub(n) as *mut SharedTrcInternal<T>;
+impl<T: ?Sized, A: Allocator> SharedTrc<T, A> {
+    /// Converts a `*const T` into `SharedTrc<T, A>`, using the provided allocator. The caller must uphold the below
+    /// safety constraints.
+    ///
+    /// # Safety
+    /// - The given pointer must be a valid pointer to `T` that came from `into_raw` or `into_raw_with_allocator`.
+    /// - The allocator must be the one that the allocation was created with.
+    /// - After `from_raw_in`, the pointer must not be accessed.
+    ///
+    /// # Examples
+    /// ```
+    /// use trc::Trc;
+    /// use trc::SharedTrc;
+    ///
+    /// let shared: SharedTrc<_> = Trc::new(100).into();
+    /// let (ptr, alloc) = SharedTrc::into_raw_with_allocator(shared);
+    ///
+    /// let shared = unsafe { SharedTrc::from_raw_in(ptr, alloc) };
+    /// assert_eq!(*shared, 100);
+    /// ```
+    pub unsafe fn from_raw_in(ptr: *const T, alloc: A) -> Self {
+        let shared = inner_from_data_ptr(ptr, core::mem::align_of_val(&*ptr));
 
         SharedTrc {
-            data: NonNull::new_unchecked(data_ptr),
+            data: NonNull::new_unchecked(shared),
+            alloc,
+        }
+    }
+}
+
+impl<T, A: Allocator> SharedTrc<T, A> {
+    /// Returns the inner value if the `SharedTrc` has exactly one atomic reference, without creating a `Trc<T>`.
+    /// Otherwise, an [`Err`] is returned with the same `SharedTrc` that was passed in.
+    /// This will succeed even if there are outstanding weak references.
+    ///
+    /// # Examples
+    /// ```
+    /// use trc::Trc;
+    /// use trc::SharedTrc;
+    ///
+    /// let shared: SharedTrc<_> = Trc::new(100).into();
+    /// let shared2 = shared.clone();
+    /// let shared = SharedTrc::try_unwrap(shared).unwrap_err();
+    ///
+    /// drop(shared2);
+    /// assert_eq!(SharedTrc::try_unwrap(shared).ok(), Some(100));
+    /// ```
+    #[inline]
+    pub fn try_unwrap(this: Self) -> Result<T, Self> {
+        if unsafe { this.data.as_ref() }
+            .atomicref
+            .compare_exchange(
+                1,
+                0,
+                core::sync::atomic::Ordering::Relaxed,
+                core::sync::atomic::Ordering::Relaxed,
+            )
+            .is_err()
+        {
+            return Err(this);
+        }
+
+        core::sync::atomic::fence(core::sync::atomic::Ordering::Acquire);
+
+        let this = ManuallyDrop::new(this);
+        unsafe {
+            let elem = ptr::read(&this.data.as_ref().data);
+
+            //Clean up implicit self-reference
+            drop(Weak {
+                data: this.data,
+                alloc: ptr::read(&this.alloc),
+            });
+
+            Ok(elem)
+        }
+    }
+
+    /// Returns the inner value if the `SharedTrc` has exactly one atomic reference, without creating a `Trc<T>`.
+    /// Otherwise, a [`None`] is returned and the `SharedTrc` is dropped.
+    /// This will succeed even if there are outstanding weak references.
+    /// If `into_inner` is called on every clone of `SharedTrc`, it is guaranteed that exactly one will return the inner
+    /// value `T`.
+    ///
+    /// # Examples
+    /// ```
+    /// use trc::Trc;
+    /// use trc::SharedTrc;
+    ///
+    /// let x: SharedTrc<_> = Trc::new(3i32).into();
+    /// let y = x.clone();
+    ///
+    /// let x_thread = std::thread::spawn(|| SharedTrc::into_inner(x));
+    /// let y_thread = std::thread::spawn(|| SharedTrc::into_inner(y));
+    ///
+    /// let x_inner_value = x_thread.join().unwrap();
+    /// let y_inner_value = y_thread.join().unwrap();
+    ///
+    /// assert!(matches!(
+    ///     (x_inner_value, y_inner_value),
+    ///     (None, Some(3)) | (Some(3), None)
+    /// ));
+    /// ```
+    #[inline]
+    pub fn into_inner(this: Self) -> Option<T> {
+        let this = ManuallyDrop::new(this);
+        let alloc = unsafe { ptr::read(&this.alloc) };
+
+        if sub_value(
+            &unsafe { this.data.as_ref() }.atomicref,
+            1,
+            core::sync::atomic::Ordering::Release,
+        ) != 1
+        {
+            return None;
+        }
+
+        core::sync::atomic::fence(core::sync::atomic::Ordering::Acquire);
+
+        let elem = unsafe { ptr::read(addr_of_mut!((*this.data.as_ptr()).data)) };
+
+        //Clean up implicit self-reference
+        drop(Weak {
+            data: this.data,
+            alloc,
+        });
+
+        Some(elem)
+    }
+}
+
+impl<T: Clone, A: Allocator> SharedTrc<T, A> {
+    /// If we have the only atomic reference to `T`, then unwrap it. Otherwise, clone `T` and return the clone.
+    ///
+    /// # Examples
+    /// ```
+    /// use trc::Trc;
+    /// use trc::SharedTrc;
+    ///
+    /// let shared: SharedTrc<_> = Trc::new(String::from("Trc")).into();
+    /// let shared2 = shared.clone();
+    ///
+    /// assert_eq!(SharedTrc::unwrap_or_clone(shared), "Trc");
+    /// assert_eq!(SharedTrc::unwrap_or_clone(shared2), "Trc");
+    /// ```
+    #[inline]
+    pub fn unwrap_or_clone(this: Self) -> T {
+        SharedTrc::try_unwrap(this).unwrap_or_else(|shared| (*shared).clone())
+    }
+}
+
+impl<T: ?Sized, A: Allocator> SharedTrc<T, A> {
+    /// Get a mutable reference to the internal data if there are no other `Trc<T>`s, `SharedTrc<T>`s or `Weak<T>`s
+    /// pointing to the same allocation.
+    ///
+    /// # Examples
+    /// ```
+    /// use trc::Trc;
+    /// use trc::SharedTrc;
+    ///
+    /// let mut shared: SharedTrc<_> = Trc::new(100).into();
+    /// *SharedTrc::get_mut(&mut shared).unwrap() = 200;
+    ///
+    /// let shared2 = shared.clone();
+    /// assert!(SharedTrc::get_mut(&mut shared).is_none());
+    /// drop(shared2);
+    /// assert_eq!(*shared, 200);
+    /// ```
+    #[inline]
+    pub fn get_mut(this: &mut Self) -> Option<&mut T> {
+        //Acquire the weakcount if it is == 1
+        if unsafe { this.data.as_ref() }
+            .weakcount
+            .compare_exchange(
+                1,
+                usize::MAX,
+                core::sync::atomic::Ordering::Acquire,
+                core::sync::atomic::Ordering::Relaxed,
+            )
+            .is_ok()
+        {
+            //Acquire the atomicref
+            let unique = unsafe { this.data.as_ref() }
+                .atomicref
+                .load(core::sync::atomic::Ordering::Acquire)
+                == 1;
+
+            //Synchronize with the previous Acquire
+            unsafe { this.data.as_ref() }
+                .weakcount
+                .store(1, core::sync::atomic::Ordering::Release);
+
+            if unique {
+                Some(unsafe { &mut (*this.data.as_ptr()).data })
+            } else {
+                None
+            }
+        } else {
+            None
+        }
+    }
+}
+
+impl<T: ?Sized + sealed::MakeMut, A: Allocator + Clone> SharedTrc<T, A> {
+    /// Makes a mutable reference into the given `SharedTrc<T>`, without creating a `Trc<T>`. See `Trc::make_mut`.
+    ///
+    /// # Examples
+    /// ```
+    /// use trc::Trc;
+    /// use trc::SharedTrc;
+    ///
+    /// let mut data: SharedTrc<_> = Trc::new(5).into();
+    /// let other_data = data.clone();
+    ///
+    /// *SharedTrc::make_mut(&mut data) += 1; // Clones inner data
+    ///
+    /// assert_eq!(*data, 6);
+    /// assert_eq!(*other_data, 5);
+    /// ```
+    #[inline]
+    pub fn make_mut(this: &mut Self) -> &mut T {
+        //Taking the atomic reference count from 1 to 0 stops `Weak<T>`s from upgrading, like `Weak::upgrade` does
+        if unsafe { this.data.as_ref() }
+            .atomicref
+            .compare_exchange(
+                1,
+                0,
+                core::sync::atomic::Ordering::Acquire,
+                core::sync::atomic::Ordering::Relaxed,
+            )
+            .is_err()
+        {
+            //Other strong references exist, so clone the data
+            *this = SharedTrc::from(T::clone_into_trc(this, &this.alloc));
+        } else if unsafe { this.data.as_ref() }
+            .weakcount
+            .load(core::sync::atomic::Ordering::Relaxed)
+            != 1
+        {
+            //Only weak references remain, so move the data out and leave them a dropped value
+            let fresh = SharedTrc::from(unsafe { T::move_into_trc(this, &this.alloc) });
+            let old = ManuallyDrop::new(core::mem::replace(this, fresh));
+
+            //Clean up implicit self-reference
+            drop(Weak {
+                data: old.data,
+                alloc: unsafe { ptr::read(&old.alloc) },
+            });
+        } else {
+            //This was the only reference, so restore the atomic reference count
+            unsafe { this.data.as_ref() }
+                .atomicref
+                .store(1, core::sync::atomic::Ordering::Release);
         }
+
+        unsafe { &mut (*this.data.as_ptr()).data }
     }
 }
 
-impl<T: ?Sized> Deref for SharedTrc<T> {
+impl<T: ?Sized, A: Allocator> Deref for SharedTrc<T, A> {
     type Target = T;
 
     /// Get an immutable reference to the internal data.
@@ -515,43 +973,43 @@ impl<T: ?Sized> Deref for SharedTrc<T> {
     }
 }
 
-impl<T: ?Sized> AsRef<T> for SharedTrc<T> {
+impl<T: ?Sized, A: Allocator> AsRef<T> for SharedTrc<T, A> {
     fn as_ref(&self) -> &T {
         SharedTrc::deref(self)
     }
 }
 
-impl<T: ?Sized> Borrow<T> for SharedTrc<T> {
+impl<T: ?Sized, A: Allocator> Borrow<T> for SharedTrc<T, A> {
     fn borrow(&self) -> &T {
         self.as_ref()
     }
 }
 
-impl<T: ?Sized + Default> Default for SharedTrc<T> {
+impl<T: Default> Default for SharedTrc<T> {
     fn default() -> Self {
         Self::from_trc(&Trc::new(Default::default()))
     }
 }
 
-impl<T: Display> Display for SharedTrc<T> {
+impl<T: ?Sized + Display, A: Allocator> Display for SharedTrc<T, A> {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         core::fmt::Display::fmt((*self).deref(), f)
     }
 }
 
-impl<T: Debug> Debug for SharedTrc<T> {
+impl<T: ?Sized + Debug, A: Allocator> Debug for SharedTrc<T, A> {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         core::fmt::Debug::fmt((*self).deref(), f)
     }
 }
 
-impl<T: ?Sized> Pointer for SharedTrc<T> {
+impl<T: ?Sized, A: Allocator> Pointer for SharedTrc<T, A> {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         core::fmt::Pointer::fmt(&addr_of!(unsafe { self.data.as_ref() }.data), f)
     }
 }
 
-impl<T: Hash> Hash for SharedTrc<T> {
+impl<T: ?Sized + Hash, A: Allocator> Hash for SharedTrc<T, A> {
     /// Pass the data contained in this `SharedTrc<T>` to the provided hasher.
     #[inline]
     fn hash<H: Hasher>(&self, state: &mut H) {
@@ -559,7 +1017,7 @@ impl<T: Hash> Hash for SharedTrc<T> {
     }
 }
 
-impl<T: PartialOrd> PartialOrd for SharedTrc<T> {
+impl<T: ?Sized + PartialOrd, A: Allocator> PartialOrd for SharedTrc<T, A> {
     /// "Greater than or equal to" comparison for two `SharedTrc<T>`s.
     ///
     /// Calls `.ge` on the data.
@@ -593,7 +1051,7 @@ impl<T: PartialOrd> PartialOrd for SharedTrc<T> {
     /// ```
     #[inline]
     fn le(&self, other: &Self) -> bool {
-        self.deref().ge(other.deref())
+        self.deref().le(other.deref())
     }
 
     /// "Greater than" comparison for two `SharedTrc<T>`s.
@@ -652,7 +1110,7 @@ impl<T: PartialOrd> PartialOrd for SharedTrc<T> {
     }
 }
 
-impl<T: Ord> Ord for SharedTrc<T> {
+impl<T: ?Sized + Ord, A: Allocator> Ord for SharedTrc<T, A> {
     /// Comparison for two `SharedTrc<T>`s. The two are compared by calling `.cmp` on the inner values.
     ///
     /// # Examples
@@ -671,9 +1129,9 @@ impl<T: Ord> Ord for SharedTrc<T> {
     }
 }
 
-impl<T: Eq> Eq for SharedTrc<T> {}
+impl<T: ?Sized + Eq, A: Allocator> Eq for SharedTrc<T, A> {}
 
-impl<T: PartialEq> PartialEq for SharedTrc<T> {
+impl<T: ?Sized + PartialEq, A: Allocator> PartialEq for SharedTrc<T, A> {
     /// Equality by value comparison for two `SharedTrc<T>`s, even if the data is in different allocoations.
     ///
     /// Calls `.eq` on the data.
@@ -712,20 +1170,20 @@ impl<T: PartialEq> PartialEq for SharedTrc<T> {
     }
 }
 
-impl<T: AsFd> AsFd for SharedTrc<T> {
+impl<T: AsFd, A: Allocator> AsFd for SharedTrc<T, A> {
     fn as_fd(&self) -> std::os::fd::BorrowedFd<'_> {
         (**self).as_fd()
     }
 }
 
-impl<T: AsRawFd> AsRawFd for SharedTrc<T> {
+impl<T: AsRawFd, A: Allocator> AsRawFd for SharedTrc<T, A> {
     fn as_raw_fd(&self) -> std::os::fd::RawFd {
         (**self).as_raw_fd()
     }
 }
 
 #[allow(deprecated)]
-impl<T: Error> Error for SharedTrc<T> {
+impl<T: ?Sized + Error, A: Allocator> Error for SharedTrc<T, A> {
     fn cause(&self) -> Option<&dyn Error> {
         (**self).cause()
     }
@@ -737,8 +1195,40 @@ impl<T: Error> Error for SharedTrc<T> {
     }
 }
 
-impl<T: ?Sized> Unpin for SharedTrc<T> {}
-impl<T: ?Sized> UnwindSafe for SharedTrc<T> {}
+impl<T: ?Sized, A: Allocator> Unpin for SharedTrc<T, A> {}
+impl<T: ?Sized, A: Allocator + UnwindSafe> UnwindSafe for SharedTrc<T, A> {}
+
+/// Get the `SharedTrcInternal<T>` which holds the data at `ptr`, keeping the pointer's metadata. `align` is the
+/// alignment of the data, see `data_offset`.
+///
+/// # Safety
+/// `ptr` must point to the data of a `SharedTrcInternal<T>`.
+#[inline]
+unsafe fn inner_from_data_ptr<T: ?Sized>(ptr: *const T, align: usize) -> *mut SharedTrcInternal<T> {
+    let offset = data_offset(align);
+
+    set_data_ptr(
+        ptr as *mut SharedTrcInternal<T>,
+        (ptr as *const u8).sub(offset) as *mut u8,
+    )
+}
+
+/// Replace the address of a possibly wide pointer, keeping its metadata (slice length or vtable).
+///
+/// # Safety
+/// The returned pointer is only valid if `data` points to an allocation that the metadata of `ptr` describes.
+#[inline]
+unsafe fn set_data_ptr<T: ?Sized, U>(mut ptr: *mut T, data: *mut U) -> *mut T {
+    //The address is always the first word of a pointer
+    ptr::write(addr_of_mut!(ptr) as *mut *mut u8, data as *mut u8);
+    ptr
+}
+
+/// Checks if the pointer is the sentinel used by `Weak::new`, which has no allocation.
+#[inline(always)]
+fn is_dangling<T: ?Sized>(ptr: *const T) -> bool {
+    ptr as *const () as usize == usize::MAX
+}
 
 #[inline(always)]
 fn sum_value(value: &AtomicUsize, offset: usize, ordering: core::sync::atomic::Ordering) -> usize {
@@ -750,10 +1240,146 @@ fn sub_value(value: &AtomicUsize, offset: usize, ordering: core::sync::atomic::O
     value.fetch_sub(offset, ordering)
 }
 
-impl<T> Trc<T> {
-    /// Creates a new `Trc<T>` from the provided data.
-    ///
-    /// # Examples
+/// Increment the weak count for a new `Weak<T>` created from a strong reference. `get_mut` locks the weak count by
+/// setting it to `usize::MAX`, so wait for it to be unlocked instead of wrapping it around.
+#[inline]
+fn increment_weakcount(weakcount: &AtomicUsize) {
+    let mut cur = weakcount.load(core::sync::atomic::Ordering::Relaxed);
+    loop {
+        if cur == usize::MAX {
+            core::hint::spin_loop();
+            cur = weakcount.load(core::sync::atomic::Ordering::Relaxed);
+            continue;
+        }
+        if cur > MAX_REFCOUNT {
+            panic!("Overflow of maximum weak reference count.");
+        }
+
+        match weakcount.compare_exchange_weak(
+            cur,
+            cur + 1,
+            core::sync::atomic::Ordering::Acquire,
+            core::sync::atomic::Ordering::Relaxed,
+        ) {
+            Ok(_) => return,
+            Err(old) => cur = old,
+        }
+    }
+}
+
+/// Allocate memory for a `SharedTrcInternal` with the provided layout, calling [`handle_alloc_error`] on failure.
+#[inline]
+fn allocate_for_layout<A: Allocator>(layout: Layout, alloc: &A) -> NonNull<u8> {
+    match try_allocate_for_layout(layout, alloc) {
+        Ok(ptr) => ptr,
+        Err(_) => handle_alloc_error(layout),
+    }
+}
+
+/// Allocate memory for a `SharedTrcInternal` with the provided layout, returning an [`AllocError`] on failure.
+#[inline]
+fn try_allocate_for_layout<A: Allocator>(
+    layout: Layout,
+    alloc: &A,
+) -> Result<NonNull<u8>, AllocError> {
+    alloc.allocate(layout).map(|ptr| ptr.cast())
+}
+
+/// Layout of a `SharedTrcInternal` holding data with the provided layout, and the offset of the data from the start of
+/// the `SharedTrcInternal`. The padding between the counts and the data depends on the data's alignment.
+/// Every computation of where the data is in an allocation, or the reverse, goes through this function.
+#[inline]
+fn inner_layout(value_layout: Layout) -> Result<(Layout, usize), LayoutError> {
+    let (layout, offset) = Layout::new::<SharedTrcInternal<()>>().extend(value_layout)?;
+    Ok((layout.pad_to_align(), offset))
+}
+
+/// Offset of the data from the start of a `SharedTrcInternal`, for data with the provided alignment.
+#[inline]
+fn data_offset(align: usize) -> usize {
+    let value_layout = Layout::from_size_align(0, align).unwrap();
+    inner_layout(value_layout).unwrap().1
+}
+
+/// Layout of a `SharedTrcInternal<[T]>` holding `len` elements.
+#[inline]
+fn slice_layout<T>(len: usize) -> Result<Layout, LayoutError> {
+    Ok(inner_layout(Layout::array::<T>(len)?)?.0)
+}
+
+/// Allocate a `SharedTrcInternal<[T]>` holding `len` uninitialized elements, with its counts initialized for a new
+/// `Trc<[T]>`. Panics if the layout overflows, and calls [`handle_alloc_error`] if the allocation fails.
+#[inline]
+fn allocate_for_slice<T, A: Allocator>(len: usize, alloc: &A) -> NonNull<SharedTrcInternal<[T]>> {
+    let layout = slice_layout::<T>(len).expect("Overflow of maximum slice length.");
+    match try_allocate_for_slice(len, alloc) {
+        Ok(ptr) => ptr,
+        Err(_) => handle_alloc_error(layout),
+    }
+}
+
+/// Allocate a `SharedTrcInternal<[T]>` holding `len` uninitialized elements, with its counts initialized for a new
+/// `Trc<[T]>`. Returns an [`AllocError`] if the layout overflows or the allocation fails.
+#[inline]
+fn try_allocate_for_slice<T, A: Allocator>(
+    len: usize,
+    alloc: &A,
+) -> Result<NonNull<SharedTrcInternal<[T]>>, AllocError> {
+    let layout = slice_layout::<T>(len).map_err(|_| AllocError)?;
+    let ptr = try_allocate_for_layout(layout, alloc)?;
+
+    let res = slice_from_raw_parts_mut(ptr.as_ptr() as *mut T, len) as *mut SharedTrcInternal<[T]>;
+    unsafe { write_counts(res, 1) };
+    Ok(unsafe { NonNull::new_unchecked(res) })
+}
+
+/// Initialize the counts of a newly allocated `SharedTrcInternal` for a new `Trc<T>`, whose local thread reference
+/// count is stored inline.
+///
+/// # Safety
+/// `ptr` must point to a newly allocated `SharedTrcInternal`.
+#[inline]
+unsafe fn write_counts<T: ?Sized>(ptr: *mut SharedTrcInternal<T>, atomicref: usize) {
+    write(addr_of_mut!((*ptr).atomicref), AtomicUsize::new(atomicref));
+    write(addr_of_mut!((*ptr).weakcount), AtomicUsize::new(1));
+    write(addr_of_mut!((*ptr).threadref), UnsafeCell::new(1));
+    #[cfg(feature = "reclaim")]
+    write(addr_of_mut!((*ptr).reclaim), None);
+    #[cfg(feature = "home")]
+    write(addr_of_mut!((*ptr).home), None);
+}
+
+/// Allocate a new local thread reference count, initialized to 1.
+/// With the `pool` feature, it is taken from this thread's pool of local thread reference counts.
+#[inline]
+fn alloc_threadref() -> NonNull<usize> {
+    #[cfg(feature = "pool")]
+    {
+        pool::alloc()
+    }
+    #[cfg(not(feature = "pool"))]
+    {
+        NonNull::from(Box::leak(Box::new(1)))
+    }
+}
+
+/// Free a local thread reference count allocated by `alloc_threadref`.
+/// With the `pool` feature, it is returned to this thread's pool of local thread reference counts.
+///
+/// # Safety
+/// The pointer must have come from `alloc_threadref` on this thread and must not be used afterwards.
+#[inline]
+unsafe fn free_threadref(threadref: NonNull<usize>) {
+    #[cfg(feature = "pool")]
+    pool::free(threadref);
+    #[cfg(not(feature = "pool"))]
+    drop(Box::from_raw(threadref.as_ptr()));
+}
+
+impl<T> Trc<T> {
+    /// Creates a new `Trc<T>` from the provided data.
+    ///
+    /// # Examples
     /// ```
     /// use trc::Trc;
     ///
@@ -762,20 +1388,7 @@ impl<T> Trc<T> {
     /// ```
     #[inline]
     pub fn new(value: T) -> Self {
-        let shareddata = SharedTrcInternal {
-            atomicref: AtomicUsize::new(1),
-            weakcount: AtomicUsize::new(1),
-            data: value,
-        };
-
-        let sbx = Box::new(shareddata);
-
-        let tbx = Box::new(1);
-
-        Trc {
-            threadref: NonNull::from(Box::leak(tbx)),
-            shared: NonNull::from(Box::leak(sbx)),
-        }
+        Self::new_in(value, Global)
     }
 
     /// Creates a new uninitialized `Trc<T>`.
@@ -794,20 +1407,40 @@ impl<T> Trc<T> {
     /// ```
     #[inline]
     pub fn new_uninit() -> Trc<MaybeUninit<T>> {
-        let shareddata = SharedTrcInternal {
-            atomicref: AtomicUsize::new(1),
-            weakcount: AtomicUsize::new(1),
-            data: MaybeUninit::<T>::uninit(),
-        };
-
-        let sbx = Box::new(shareddata);
+        Self::new_uninit_in(Global)
+    }
 
-        let tbx = Box::new(1);
+    /// Creates a new `Trc<T>` from the provided data, returning an error if the allocation fails.
+    ///
+    /// # Examples
+    /// ```
+    /// use trc::Trc;
+    ///
+    /// let trc = Trc::try_new(100).unwrap();
+    /// assert_eq!(*trc, 100);
+    /// ```
+    #[inline]
+    pub fn try_new(value: T) -> Result<Self, AllocError> {
+        Self::try_new_in(value, Global)
+    }
 
-        Trc {
-            threadref: NonNull::from(Box::leak(tbx)),
-            shared: NonNull::from(Box::leak(sbx)),
-        }
+    /// Creates a new uninitialized `Trc<T>`, returning an error if the allocation fails.
+    ///
+    /// # Examples
+    /// ```
+    /// use trc::Trc;
+    ///
+    /// let mut trc = Trc::try_new_uninit().unwrap();
+    ///
+    /// Trc::get_mut(&mut trc).unwrap().write(5);
+    ///
+    /// let five = unsafe { trc.assume_init() };
+    ///
+    /// assert_eq!(*five, 5);
+    /// ```
+    #[inline]
+    pub fn try_new_uninit() -> Result<Trc<MaybeUninit<T>>, AllocError> {
+        Self::try_new_uninit_in(Global)
     }
 
     /// Creates a new cyclic `Trc<T>` from the provided data. It allows the storage of `Weak<T>` which points the the allocation
@@ -828,18 +1461,150 @@ impl<T> Trc<T> {
     where
         F: FnOnce(&Weak<T>) -> T,
     {
-        let shareddata: NonNull<_> = Box::leak(Box::new(SharedTrcInternal {
-            atomicref: AtomicUsize::new(0),
-            weakcount: AtomicUsize::new(1),
-            data: core::mem::MaybeUninit::<T>::uninit(),
-        }))
-        .into();
+        Self::new_cyclic_in(data_fn, Global)
+    }
+
+    /// Creates a new `Pin<Trc<T>>`. If `T` does not implement [`Unpin`], then the data will be pinned in memory and unable to be moved.
+    #[inline]
+    pub fn pin(data: T) -> Pin<Trc<T>> {
+        unsafe { Pin::new_unchecked(Trc::new(data)) }
+    }
+}
+
+impl<T, A: Allocator> Trc<T, A> {
+    /// Creates a new `Trc<T, A>` from the provided data, in the provided allocator.
+    ///
+    /// # Examples
+    /// ```
+    /// # #![cfg_attr(feature = "nightly", feature(allocator_api))]
+    /// use trc::Trc;
+    /// use allocator_api2::alloc::Global;
+    ///
+    /// let trc = Trc::new_in(100, Global);
+    /// assert_eq!(*trc, 100);
+    /// ```
+    #[inline]
+    pub fn new_in(value: T, alloc: A) -> Self {
+        let layout = Layout::new::<SharedTrcInternal<T>>();
+        let shared = allocate_for_layout(layout, &alloc).cast::<SharedTrcInternal<T>>();
+
+        unsafe { Trc::init_inline(shared, value, alloc) }
+    }
+
+    /// Creates a new `Trc<T, A>` from the provided data, in the provided allocator, returning an error if the
+    /// allocation fails.
+    ///
+    /// # Examples
+    /// ```
+    /// # #![cfg_attr(feature = "nightly", feature(allocator_api))]
+    /// use trc::Trc;
+    /// use allocator_api2::alloc::Global;
+    ///
+    /// let trc = Trc::try_new_in(100, Global).unwrap();
+    /// assert_eq!(*trc, 100);
+    /// ```
+    #[inline]
+    pub fn try_new_in(value: T, alloc: A) -> Result<Self, AllocError> {
+        let layout = Layout::new::<SharedTrcInternal<T>>();
+        let shared = try_allocate_for_layout(layout, &alloc)?.cast::<SharedTrcInternal<T>>();
+
+        Ok(unsafe { Trc::init_inline(shared, value, alloc) })
+    }
+
+    /// Creates a new uninitialized `Trc<T, A>` in the provided allocator, returning an error if the allocation fails.
+    ///
+    /// # Examples
+    /// ```
+    /// # #![cfg_attr(feature = "nightly", feature(allocator_api))]
+    /// use trc::Trc;
+    /// use allocator_api2::alloc::Global;
+    ///
+    /// let mut trc = Trc::<i32>::try_new_uninit_in(Global).unwrap();
+    ///
+    /// Trc::get_mut(&mut trc).unwrap().write(5);
+    ///
+    /// let five = unsafe { trc.assume_init() };
+    ///
+    /// assert_eq!(*five, 5);
+    /// ```
+    #[inline]
+    pub fn try_new_uninit_in(alloc: A) -> Result<Trc<MaybeUninit<T>, A>, AllocError> {
+        Trc::try_new_in(MaybeUninit::<T>::uninit(), alloc)
+    }
+
+    /// Initialize a newly allocated `SharedTrcInternal<T>` with `value` and wrap it in a `Trc<T, A>`.
+    ///
+    /// # Safety
+    /// `shared` must be a fresh allocation from `alloc` with the layout of `SharedTrcInternal<T>`.
+    #[inline]
+    unsafe fn init_inline(shared: NonNull<SharedTrcInternal<T>>, value: T, alloc: A) -> Self {
+        write(
+            shared.as_ptr(),
+            SharedTrcInternal {
+                atomicref: AtomicUsize::new(1),
+                weakcount: AtomicUsize::new(1),
+                threadref: UnsafeCell::new(1),
+                #[cfg(feature = "reclaim")]
+                reclaim: None,
+                #[cfg(feature = "home")]
+                home: None,
+                data: value,
+            },
+        );
+
+        Trc::from_inner_inline(shared, alloc)
+    }
+
+    /// Creates a new uninitialized `Trc<T, A>` in the provided allocator.
+    ///
+    /// # Examples
+    /// ```
+    /// # #![cfg_attr(feature = "nightly", feature(allocator_api))]
+    /// use trc::Trc;
+    /// use allocator_api2::alloc::Global;
+    ///
+    /// let mut trc = Trc::<i32>::new_uninit_in(Global);
+    ///
+    /// Trc::get_mut(&mut trc).unwrap().write(5);
+    ///
+    /// let five = unsafe { trc.assume_init() };
+    ///
+    /// assert_eq!(*five, 5);
+    /// ```
+    #[inline]
+    pub fn new_uninit_in(alloc: A) -> Trc<MaybeUninit<T>, A> {
+        Trc::new_in(MaybeUninit::<T>::uninit(), alloc)
+    }
 
-        let init_ptr: NonNull<SharedTrcInternal<T>> = shareddata.cast();
+    /// Creates a new cyclic `Trc<T, A>` from the provided data, in the provided allocator. See `Trc::new_cyclic`.
+    ///
+    /// # Examples
+    /// ```
+    /// # #![cfg_attr(feature = "nightly", feature(allocator_api))]
+    /// use trc::Trc;
+    /// use trc::Weak;
+    /// use allocator_api2::alloc::Global;
+    ///
+    /// struct T(Weak<T>);
+    ///
+    /// let trc = Trc::new_cyclic_in(|x| T(x.clone()), Global);
+    /// ```
+    #[inline]
+    pub fn new_cyclic_in<F>(data_fn: F, alloc: A) -> Self
+    where
+        F: FnOnce(&Weak<T, A>) -> T,
+    {
+        let layout = Layout::new::<SharedTrcInternal<MaybeUninit<T>>>();
+        let init_ptr = allocate_for_layout(layout, &alloc).cast::<SharedTrcInternal<T>>();
+        unsafe { write_counts(init_ptr.as_ptr(), 0) };
 
-        let weak: Weak<T> = Weak { data: init_ptr };
+        let weak: Weak<T, A> = Weak {
+            data: init_ptr,
+            alloc,
+        };
         let data = data_fn(&weak);
-        core::mem::forget(weak);
+        let weak = ManuallyDrop::new(weak);
+        let alloc = unsafe { ptr::read(&weak.alloc) };
 
         unsafe {
             let ptr = init_ptr.as_ptr();
@@ -854,18 +1619,13 @@ impl<T> Trc<T> {
             }
         }
 
-        let tbx = Box::new(1);
-
-        Trc {
-            threadref: NonNull::from(Box::leak(tbx)),
-            shared: init_ptr,
-        }
+        unsafe { Trc::from_inner_inline(init_ptr, alloc) }
     }
 
-    /// Creates a new `Pin<Trc<T>>`. If `T` does not implement [`Unpin`], then the data will be pinned in memory and unable to be moved.
+    /// Creates a new `Pin<Trc<T, A>>` in the provided allocator. If `T` does not implement [`Unpin`], then the data will be pinned in memory and unable to be moved.
     #[inline]
-    pub fn pin(data: T) -> Pin<Trc<T>> {
-        unsafe { Pin::new_unchecked(Trc::new(data)) }
+    pub fn pin_in(data: T, alloc: A) -> Pin<Trc<T, A>> {
+        unsafe { Pin::new_unchecked(Trc::new_in(data, alloc)) }
     }
 
     /// Returns the inner value if the `Trc` has exactly one atomic and local reference.
@@ -892,7 +1652,7 @@ impl<T> Trc<T> {
     /// let inner = Trc::try_unwrap(trc).ok();
     /// ```
     #[inline]
-    pub fn try_unwrap(mut this: Self) -> Result<T, Self> {
+    pub fn try_unwrap(this: Self) -> Result<T, Self> {
         if unsafe { this.shared.as_ref() }
             .atomicref
             .load(core::sync::atomic::Ordering::Acquire)
@@ -901,17 +1661,20 @@ impl<T> Trc<T> {
         {
             return Err(this);
         }
+        let mut this = ManuallyDrop::new(this);
         *unsafe { this.threadref.as_mut() } -= 1;
 
         core::sync::atomic::fence(core::sync::atomic::Ordering::Acquire);
 
         unsafe {
             let elem = ptr::read(&this.shared.as_ref().data);
-            drop(Box::from_raw(this.threadref.as_ptr()));
+            Trc::release_threadref(&this);
 
             //Clean up implicit self-reference
-            drop(Weak { data: this.shared });
-            core::mem::forget(this);
+            drop(Weak {
+                data: this.shared,
+                alloc: ptr::read(&this.alloc),
+            });
 
             Ok(elem)
         }
@@ -950,30 +1713,41 @@ impl<T> Trc<T> {
     ///
     #[inline]
     pub fn into_inner(this: Self) -> Option<T> {
-        let this = core::mem::ManuallyDrop::new(this);
+        let mut this = core::mem::ManuallyDrop::new(this);
+        let alloc = unsafe { ptr::read(&this.alloc) };
+
+        //Other `Trc<T>`s on this thread still own the local reference
+        if *unsafe { this.threadref.as_ref() } != 1 {
+            *unsafe { this.threadref.as_mut() } -= 1;
+            return None;
+        }
+        *unsafe { this.threadref.as_mut() } -= 1;
+        unsafe { Trc::release_threadref(&this) };
 
         if sub_value(
             &unsafe { this.shared.as_ref() }.atomicref,
             1,
             core::sync::atomic::Ordering::Release,
         ) != 1
-            || *unsafe { this.threadref.as_ref() } != 1
         {
-            drop(unsafe { Box::from_raw(this.threadref.as_ptr()) });
             return None;
         }
 
         core::sync::atomic::fence(core::sync::atomic::Ordering::Acquire);
 
         let elem = unsafe { core::ptr::read(addr_of_mut!((*this.shared.as_ptr()).data)) };
-        drop(unsafe { Box::from_raw(this.threadref.as_ptr()) });
 
         //Clean up implicit self-reference
-        drop(Weak { data: this.shared });
+        drop(Weak {
+            data: this.shared,
+            alloc,
+        });
 
         Some(elem)
     }
+}
 
+impl<T: ?Sized> Trc<T> {
     /// Converts a `*const T` into `Trc<T>`. The caller must uphold the below safety constraints.
     /// To avoid a memory leak, be sure to call `from_raw` to reclaim the allocation.
     ///
@@ -992,18 +1766,46 @@ impl<T> Trc<T> {
     ///
     /// unsafe { Trc::from_raw(ptr) };
     /// ```
+    ///
+    /// Unsized types such as slices, strings and trait objects keep their length or vtable:
+    /// ```
+    /// use trc::Trc;
+    ///
+    /// let trc = Trc::<[u8]>::from(&b"Trc"[..]);
+    /// let ptr = Trc::into_raw(trc);
+    ///
+    /// let trc = unsafe { Trc::from_raw(ptr) };
+    /// assert_eq!(&*trc, b"Trc");
+    /// ```
     pub unsafe fn from_raw(ptr: *const T) -> Self {
-        let tbx = Box::new(1);
-
-        let layout = Layout::new::<SharedTrcInternal<()>>();
-        let n = layout.size();
+        Self::from_raw_in(ptr, Global)
+    }
+}
 
-        let data_ptr = (ptr as *const u8).sub(n) as *mut SharedTrcInternal<T>;
+impl<T: ?Sized, A: Allocator> Trc<T, A> {
+    /// Converts a `*const T` into `Trc<T, A>`, using the provided allocator. The caller must uphold the below safety constraints.
+    /// To avoid a memory leak, be sure to call `from_raw_in` to reclaim the allocation.
+    ///
+    /// # Safety
+    /// - The given pointer must be a valid pointer to `T` that came from `into_raw` or `into_raw_with_allocator`.
+    /// - The allocator must be the one that the allocation was created with.
+    /// - After `from_raw_in`, the pointer must not be accessed.
+    ///
+    /// # Examples
+    /// ```
+    /// use trc::Trc;
+    ///
+    /// let trc = Trc::new(100);
+    /// let (ptr, alloc) = Trc::into_raw_with_allocator(trc);
+    ///
+    /// assert_eq!(unsafe { *ptr }, 100);
+    ///
+    /// unsafe { Trc::from_raw_in(ptr, alloc) };
+    /// ```
+    pub unsafe fn from_raw_in(ptr: *const T, alloc: A) -> Self {
+        let shared = inner_from_data_ptr(ptr, core::mem::align_of_val(&*ptr));
 
-        Trc {
-            threadref: NonNull::from(Box::leak(tbx)),
-            shared: NonNull::new_unchecked(data_ptr),
-        }
+        Trc::from_shared(NonNull::new_unchecked(shared), alloc)
     }
 }
 
@@ -1023,45 +1825,17 @@ impl<T> Trc<[T]> {
     /// assert_eq!(*five, 5);
     /// ```
     pub fn new_uninit_slice(len: usize) -> Trc<[MaybeUninit<T>]> {
-        let value_layout = Layout::array::<T>(len).unwrap();
-        let layout = Layout::new::<SharedTrcInternal<()>>()
-            .extend(value_layout)
-            .unwrap()
-            .0
-            .pad_to_align();
-
-        let res = slice_from_raw_parts_mut(unsafe { alloc(layout) } as *mut T, len)
-            as *mut SharedTrcInternal<[MaybeUninit<T>]>;
-        unsafe { write(&mut (*res).atomicref, AtomicUsize::new(1)) };
-        unsafe { write(&mut (*res).weakcount, AtomicUsize::new(1)) };
-
-        let elems = unsafe { addr_of_mut!((*res).data) } as *mut MaybeUninit<T>;
-        for i in 0..len {
-            unsafe { write(elems.add(i), MaybeUninit::<T>::uninit()) };
-        }
-        let tbx = Box::new(1);
-
-        Trc {
-            threadref: NonNull::from(Box::leak(tbx)),
-            shared: unsafe { NonNull::new_unchecked(res) },
-        }
+        Self::new_uninit_slice_in(len, Global)
     }
-}
 
-impl<T> Trc<MaybeUninit<T>> {
-    /// Converts to `Trc<T>`.
-    ///
-    /// # Safety
-    /// As with `MaybeUninit::assume_init`, it is up to the caller to guarantee that the inner value really is in an initialized state.
-    /// Calling this when the content is not yet fully initialized causes immediate undefined behavior.
+    /// Constructs a new Trc slice with uninitialized contents, returning an error if the allocation fails.
     ///
     /// # Examples
     /// ```
     /// use trc::Trc;
     ///
-    /// let mut values = Trc::<[u32]>::new_uninit_slice(3);
+    /// let mut values = Trc::<[u32]>::try_new_uninit_slice(3).unwrap();
     ///
-    /// // Deferred initialization:
     /// let data = Trc::get_mut(&mut values).unwrap();
     /// data[0].write(1);
     /// data[1].write(2);
@@ -1071,29 +1845,39 @@ impl<T> Trc<MaybeUninit<T>> {
     ///
     /// assert_eq!(*values, [1, 2, 3])
     /// ```
-    pub unsafe fn assume_init(self) -> Trc<T> {
-        let threadref = self.threadref;
-        Trc {
-            shared: NonNull::new_unchecked(ManuallyDrop::new(self).shared.as_ptr().cast()),
-            threadref,
-        }
+    pub fn try_new_uninit_slice(len: usize) -> Result<Trc<[MaybeUninit<T>]>, AllocError> {
+        Self::try_new_uninit_slice_in(len, Global)
     }
-}
 
-impl<T> Trc<[MaybeUninit<T>]> {
-    /// Converts to `Trc<[T]>`.
+    /// Constructs a new `Trc<[T]>` from an iterator with an exact size, returning an error if the allocation fails.
     ///
-    /// # Safety
-    /// As with `MaybeUninit::assume_init`, it is up to the caller to guarantee that the inner value really is in an initialized state.
-    /// Calling this when the content is not yet fully initialized causes immediate undefined behavior.
+    /// # Examples
+    /// ```
+    /// use trc::Trc;
+    ///
+    /// let trc = Trc::<[i32]>::try_from_iter(vec![1, 2, 3]).unwrap();
+    /// assert_eq!(&*trc, [1, 2, 3]);
+    /// ```
+    pub fn try_from_iter<I>(iter: I) -> Result<Self, AllocError>
+    where
+        I: IntoIterator<Item = T>,
+        I::IntoIter: ExactSizeIterator,
+    {
+        Self::try_from_iter_in(iter, Global)
+    }
+}
+
+impl<T, A: Allocator> Trc<[T], A> {
+    /// Constructs a new Trc slice with uninitialized contents, in the provided allocator.
     ///
     /// # Examples
     /// ```
+    /// # #![cfg_attr(feature = "nightly", feature(allocator_api))]
     /// use trc::Trc;
+    /// use allocator_api2::alloc::Global;
     ///
-    /// let mut values = Trc::<[u32]>::new_uninit_slice(3);
+    /// let mut values = Trc::<[u32]>::new_uninit_slice_in(3, Global);
     ///
-    /// // Deferred initialization:
     /// let data = Trc::get_mut(&mut values).unwrap();
     /// data[0].write(1);
     /// data[1].write(2);
@@ -1103,49 +1887,159 @@ impl<T> Trc<[MaybeUninit<T>]> {
     ///
     /// assert_eq!(*values, [1, 2, 3])
     /// ```
-    pub unsafe fn assume_init(self) -> Trc<[T]> {
-        let threadref = self.threadref;
-        Trc {
-            shared: NonNull::new_unchecked(ManuallyDrop::new(self).shared.as_ptr() as _),
-            threadref,
-        }
+    pub fn new_uninit_slice_in(len: usize, alloc: A) -> Trc<[MaybeUninit<T>], A> {
+        let res = allocate_for_slice::<MaybeUninit<T>, A>(len, &alloc);
+
+        unsafe { Trc::from_inner_inline(res, alloc) }
     }
-}
 
-impl<T: ?Sized> Trc<T> {
-    /// Return the local thread reference count of the object, which is how many `Trc<T>`s in this thread point to the data referenced by this `Trc<T>`.
+    /// Constructs a new Trc slice with uninitialized contents, in the provided allocator, returning an error if the
+    /// allocation fails.
     ///
     /// # Examples
     /// ```
+    /// # #![cfg_attr(feature = "nightly", feature(allocator_api))]
     /// use trc::Trc;
+    /// use allocator_api2::alloc::Global;
     ///
-    /// let trc = Trc::new(100);
-    /// assert!(Trc::local_count(&trc) == 1)
+    /// let values = Trc::<[u32]>::try_new_uninit_slice_in(3, Global).unwrap();
+    /// assert_eq!(values.len(), 3);
     /// ```
-    #[inline]
-    pub fn local_count(this: &Self) -> usize {
-        *unsafe { this.threadref.as_ref() }
+    pub fn try_new_uninit_slice_in(
+        len: usize,
+        alloc: A,
+    ) -> Result<Trc<[MaybeUninit<T>], A>, AllocError> {
+        let res = try_allocate_for_slice::<MaybeUninit<T>, A>(len, &alloc)?;
+
+        Ok(unsafe { Trc::from_inner_inline(res, alloc) })
     }
 
-    /// Return the atomic reference count of the object. This is how many threads are using the data referenced by this `Trc<T>`.
+    /// Constructs a new `Trc<[T], A>` from an iterator with an exact size, in the provided allocator, returning an
+    /// error if the allocation fails. Panics if the iterator yields a different number of elements than its `len`.
+    ///
+    /// # Examples
     /// ```
-    /// use std::thread;
+    /// # #![cfg_attr(feature = "nightly", feature(allocator_api))]
     /// use trc::Trc;
-    /// use trc::SharedTrc;
-    ///
-    /// let trc = Trc::new(100);
-    /// let shared = SharedTrc::from_trc(&trc);
-    ///
-    /// let handle = thread::spawn(move || {
-    ///     let mut trc = SharedTrc::to_trc(shared);
-    ///     assert_eq!(Trc::atomic_count(&trc), 2);
-    /// });
+    /// use allocator_api2::alloc::Global;
     ///
-    /// handle.join().unwrap();
-    /// assert_eq!(Trc::atomic_count(&trc), 1);
-    /// assert_eq!(*trc, 100);
+    /// let trc = Trc::<[i32]>::try_from_iter_in(vec![1, 2, 3], Global).unwrap();
+    /// assert_eq!(&*trc, [1, 2, 3]);
     /// ```
-    #[inline]
+    pub fn try_from_iter_in<I>(iter: I, alloc: A) -> Result<Self, AllocError>
+    where
+        I: IntoIterator<Item = T>,
+        I::IntoIter: ExactSizeIterator,
+    {
+        let iterator = iter.into_iter();
+        let mut builder = SliceBuilder::try_new(iterator.len(), &alloc)?;
+        builder.extend(iterator);
+        let res = builder.finish();
+
+        Ok(unsafe { Trc::from_inner_inline(res, alloc) })
+    }
+}
+
+impl<T, A: Allocator> Trc<MaybeUninit<T>, A> {
+    /// Converts to `Trc<T>`.
+    ///
+    /// # Safety
+    /// As with `MaybeUninit::assume_init`, it is up to the caller to guarantee that the inner value really is in an initialized state.
+    /// Calling this when the content is not yet fully initialized causes immediate undefined behavior.
+    ///
+    /// # Examples
+    /// ```
+    /// use trc::Trc;
+    ///
+    /// let mut values = Trc::<[u32]>::new_uninit_slice(3);
+    ///
+    /// // Deferred initialization:
+    /// let data = Trc::get_mut(&mut values).unwrap();
+    /// data[0].write(1);
+    /// data[1].write(2);
+    /// data[2].write(3);
+    ///
+    /// let values = unsafe { values.assume_init() };
+    ///
+    /// assert_eq!(*values, [1, 2, 3])
+    /// ```
+    pub unsafe fn assume_init(self) -> Trc<T, A> {
+        let this = ManuallyDrop::new(self);
+        Trc {
+            shared: this.shared.cast(),
+            threadref: this.threadref,
+            alloc: ptr::read(&this.alloc),
+        }
+    }
+}
+
+impl<T, A: Allocator> Trc<[MaybeUninit<T>], A> {
+    /// Converts to `Trc<[T]>`.
+    ///
+    /// # Safety
+    /// As with `MaybeUninit::assume_init`, it is up to the caller to guarantee that the inner value really is in an initialized state.
+    /// Calling this when the content is not yet fully initialized causes immediate undefined behavior.
+    ///
+    /// # Examples
+    /// ```
+    /// use trc::Trc;
+    ///
+    /// let mut values = Trc::<[u32]>::new_uninit_slice(3);
+    ///
+    /// // Deferred initialization:
+    /// let data = Trc::get_mut(&mut values).unwrap();
+    /// data[0].write(1);
+    /// data[1].write(2);
+    /// data[2].write(3);
+    ///
+    /// let values = unsafe { values.assume_init() };
+    ///
+    /// assert_eq!(*values, [1, 2, 3])
+    /// ```
+    pub unsafe fn assume_init(self) -> Trc<[T], A> {
+        let this = ManuallyDrop::new(self);
+        Trc {
+            shared: NonNull::new_unchecked(this.shared.as_ptr() as _),
+            threadref: this.threadref,
+            alloc: ptr::read(&this.alloc),
+        }
+    }
+}
+
+impl<T: ?Sized, A: Allocator> Trc<T, A> {
+    /// Return the local thread reference count of the object, which is how many `Trc<T>`s in this thread point to the data referenced by this `Trc<T>`.
+    ///
+    /// # Examples
+    /// ```
+    /// use trc::Trc;
+    ///
+    /// let trc = Trc::new(100);
+    /// assert!(Trc::local_count(&trc) == 1)
+    /// ```
+    #[inline]
+    pub fn local_count(this: &Self) -> usize {
+        *unsafe { this.threadref.as_ref() }
+    }
+
+    /// Return the atomic reference count of the object. This is how many threads are using the data referenced by this `Trc<T>`.
+    /// ```
+    /// use std::thread;
+    /// use trc::Trc;
+    /// use trc::SharedTrc;
+    ///
+    /// let trc = Trc::new(100);
+    /// let shared = SharedTrc::from_trc(&trc);
+    ///
+    /// let handle = thread::spawn(move || {
+    ///     let mut trc = SharedTrc::to_trc(shared);
+    ///     assert_eq!(Trc::atomic_count(&trc), 2);
+    /// });
+    ///
+    /// handle.join().unwrap();
+    /// assert_eq!(Trc::atomic_count(&trc), 1);
+    /// assert_eq!(*trc, 100);
+    /// ```
+    #[inline]
     pub fn atomic_count(this: &Self) -> usize {
         unsafe { this.shared.as_ref() }
             .atomicref
@@ -1186,7 +2080,7 @@ impl<T: ?Sized> Trc<T> {
     /// ```
     #[inline]
     pub fn ptr_eq(this: &Self, other: &Self) -> bool {
-        this.shared.as_ptr() == other.shared.as_ptr()
+        this.shared.cast::<u8>() == other.shared.cast::<u8>()
     }
 
     /// Gets the raw pointer to the most inner layer of `Trc<T>`.
@@ -1266,15 +2160,177 @@ impl<T: ?Sized> Trc<T> {
     /// unsafe { Trc::from_raw(ptr) };
     /// ```
     pub fn into_raw(this: Self) -> *const T {
-        let ptr = Self::as_ptr(&this);
+        SharedTrc::into_raw(SharedTrc::from(this))
+    }
 
-        drop(unsafe { Box::from_raw(this.threadref.as_ptr()) });
-        forget(this);
-        ptr
+    /// Converts a `Trc<T, A>` into `*const T`, without freeing the allocation, and returns the allocator.
+    /// To avoid a memory leak, be sure to call `from_raw_in` to reclaim the allocation.
+    ///
+    /// # Examples
+    /// ```
+    /// use trc::Trc;
+    ///
+    /// let trc = Trc::new(100);
+    /// let (ptr, alloc) = Trc::into_raw_with_allocator(trc);
+    ///
+    /// assert_eq!(unsafe { *ptr }, 100);
+    ///
+    /// unsafe { Trc::from_raw_in(ptr, alloc) };
+    /// ```
+    pub fn into_raw_with_allocator(this: Self) -> (*const T, A) {
+        SharedTrc::into_raw_with_allocator(SharedTrc::from(this))
+    }
+
+    /// Returns a reference to the underlying allocator.
+    ///
+    /// # Examples
+    /// ```
+    /// # #![cfg_attr(feature = "nightly", feature(allocator_api))]
+    /// use trc::Trc;
+    /// use allocator_api2::alloc::Global;
+    ///
+    /// let trc = Trc::new(100);
+    /// let _: &Global = Trc::allocator(&trc);
+    /// ```
+    #[inline]
+    pub fn allocator(this: &Self) -> &A {
+        &this.alloc
+    }
+}
+
+impl<T: ?Sized, A: Allocator> Trc<T, A> {
+    /// Create a `Trc<T>` which uses the local thread reference count stored inline in the allocation.
+    ///
+    /// # Safety
+    /// The allocation must be newly created on this thread with `alloc`, with the inline local thread reference count set to 1.
+    #[inline]
+    unsafe fn from_inner_inline(shared: NonNull<SharedTrcInternal<T>>, alloc: A) -> Self {
+        let threadref =
+            NonNull::new_unchecked(UnsafeCell::raw_get(addr_of!((*shared.as_ptr()).threadref)));
+
+        Trc {
+            shared,
+            threadref,
+            alloc,
+        }
+    }
+
+    /// Release the local reference held by this `Trc<T>` while keeping one atomic reference to the allocation, which is
+    /// returned along with the allocator.
+    #[inline]
+    fn into_shared_parts(this: Self) -> (NonNull<SharedTrcInternal<T>>, A) {
+        let mut this = ManuallyDrop::new(this);
+        let alloc = unsafe { ptr::read(&this.alloc) };
+
+        *unsafe { this.threadref.as_mut() } -= 1;
+        if *unsafe { this.threadref.as_ref() } == 0 {
+            //The atomic reference held by this thread is transferred
+            unsafe { Trc::release_threadref(&this) };
+        } else {
+            let prev = sum_value(
+                &unsafe { this.shared.as_ref() }.atomicref,
+                1,
+                core::sync::atomic::Ordering::Acquire,
+            );
+            if prev > MAX_REFCOUNT {
+                panic!("Overflow of maximum strong reference count.");
+            }
+
+            //The remaining local references may be reused by `SharedTrc::to_trc` on this thread
+            #[cfg(feature = "registry")]
+            Trc::register(&this);
+        }
+
+        (this.shared, alloc)
+    }
+
+    /// Create a `Trc<T>` on this thread which takes ownership of one atomic reference.
+    ///
+    /// With the `registry` feature, if this thread already has a local thread reference count for the allocation,
+    /// it is reused and the atomic reference is released. Otherwise, a new local thread reference count is allocated.
+    ///
+    /// # Safety
+    /// The caller must own an atomic reference to the allocation, which is transferred to the returned `Trc<T>`.
+    #[inline]
+    unsafe fn from_shared(shared: NonNull<SharedTrcInternal<T>>, alloc: A) -> Self {
+        #[cfg(feature = "registry")]
+        if let Some(threadref) = acquire_registered_threadref(shared) {
+            //This thread already holds an atomic reference, so the count cannot reach zero here
+            sub_value(
+                &shared.as_ref().atomicref,
+                1,
+                core::sync::atomic::Ordering::Release,
+            );
+            return Trc {
+                shared,
+                threadref,
+                alloc,
+            };
+        }
+
+        let threadref = alloc_threadref();
+
+        #[cfg(feature = "registry")]
+        registry::insert(shared.cast::<u8>().as_ptr() as usize, threadref);
+
+        Trc {
+            shared,
+            threadref,
+            alloc,
+        }
+    }
+
+    /// Register the local thread reference count in this thread's registry. A `Trc<T>` is only registered once a
+    /// `SharedTrc<T>` or `Weak<T>` which could be converted back into a `Trc<T>` on this thread is created from it,
+    /// so that `Trc::new` does not touch the registry.
+    #[cfg(feature = "registry")]
+    #[inline]
+    fn register(this: &Self) {
+        registry::insert(this.shared.cast::<u8>().as_ptr() as usize, this.threadref);
+    }
+
+    /// Check if this `Trc<T>` uses the local thread reference count stored inline in the allocation.
+    #[inline]
+    fn is_inline_threadref(this: &Self) -> bool {
+        let inline = unsafe { UnsafeCell::raw_get(addr_of!((*this.shared.as_ptr()).threadref)) };
+        ptr::eq(this.threadref.as_ptr(), inline)
+    }
+
+    /// Free the local thread reference count once it has reached zero. The inline local thread reference count
+    /// is part of the allocation, and so is not freed.
+    ///
+    /// # Safety
+    /// The local thread reference count must be zero, and must not be used afterwards.
+    #[inline]
+    unsafe fn release_threadref(this: &Self) {
+        #[cfg(feature = "registry")]
+        registry::remove(this.shared.cast::<u8>().as_ptr() as usize);
+
+        if !Trc::is_inline_threadref(this) {
+            free_threadref(this.threadref);
+        }
+    }
+}
+
+/// Increment and return the local thread reference count registered for the allocation on this thread.
+/// Returns [`None`] if this thread has no `Trc<T>` to the allocation.
+///
+/// # Safety
+/// `shared` must point to a valid allocation.
+#[cfg(feature = "registry")]
+#[inline]
+unsafe fn acquire_registered_threadref<T: ?Sized>(
+    shared: NonNull<SharedTrcInternal<T>>,
+) -> Option<NonNull<usize>> {
+    let threadref = registry::get(shared.cast::<u8>().as_ptr() as usize)?;
+    *threadref.as_ptr() += 1;
+    if *threadref.as_ptr() > MAX_REFCOUNT {
+        panic!("Overflow of maximum strong reference count.");
     }
+    Some(threadref)
 }
 
-impl<T: Clone> Trc<T> {
+impl<T: Clone, A: Allocator> Trc<T, A> {
     /// If we have the only strong and local reference to `T`, then unwrap it. Otherwise, clone `T` and return the clone.
     /// If `trc_t` is of type `Trc<T>`, this function is functionally equivalent to `(*trc_t).clone()`, but will avoid cloning the inner
     /// value where possible.
@@ -1300,7 +2356,150 @@ impl<T: Clone> Trc<T> {
     }
 }
 
-impl<T: ?Sized> Trc<T> {
+mod sealed {
+    use super::*;
+
+    /// Types whose value `Trc::make_mut` can clone or move into a new allocation: `T: Clone` and `[T]` where `T: Clone`.
+    pub trait MakeMut {
+        /// Create a new `Trc` holding a clone of `self`, in the provided allocator.
+        fn clone_into_trc<A: Allocator + Clone>(&self, alloc: &A) -> Trc<Self, A>;
+
+        /// Create a new `Trc` holding a bitwise copy of `self`, in the provided allocator.
+        ///
+        /// # Safety
+        /// `self` must not be used or dropped afterwards.
+        unsafe fn move_into_trc<A: Allocator + Clone>(&self, alloc: &A) -> Trc<Self, A>;
+    }
+
+    impl<T: Clone> MakeMut for T {
+        fn clone_into_trc<A: Allocator + Clone>(&self, alloc: &A) -> Trc<T, A> {
+            Trc::new_in(self.clone(), alloc.clone())
+        }
+
+        unsafe fn move_into_trc<A: Allocator + Clone>(&self, alloc: &A) -> Trc<T, A> {
+            let fresh = Trc::<T, A>::new_uninit_in(alloc.clone());
+            ptr::copy_nonoverlapping(
+                self,
+                addr_of_mut!((*fresh.shared.as_ptr()).data) as *mut T,
+                1,
+            );
+            fresh.assume_init()
+        }
+    }
+
+    impl<T: Clone> MakeMut for [T] {
+        fn clone_into_trc<A: Allocator + Clone>(&self, alloc: &A) -> Trc<[T], A> {
+            let fresh = create_from_iterator_exact(self.iter().cloned(), alloc);
+            unsafe { Trc::from_inner_inline(fresh, alloc.clone()) }
+        }
+
+        unsafe fn move_into_trc<A: Allocator + Clone>(&self, alloc: &A) -> Trc<[T], A> {
+            let fresh = allocate_for_slice::<T, A>(self.len(), alloc);
+            ptr::copy_nonoverlapping(
+                self.as_ptr(),
+                addr_of_mut!((*fresh.as_ptr()).data) as *mut T,
+                self.len(),
+            );
+            Trc::from_inner_inline(fresh, alloc.clone())
+        }
+    }
+}
+
+impl<T: ?Sized + sealed::MakeMut, A: Allocator + Clone> Trc<T, A> {
+    /// Makes a mutable reference into the given `Trc<T>`. This is implemented for `T: Clone` and for `[T]` where `T: Clone`.
+    /// If there are other `Trc<T>`s on this thread, or other threads (or `SharedTrc<T>`s) hold the data, the inner value
+    /// is cloned into a new allocation to ensure unique ownership. This is also referred to as clone-on-write.
+    /// If there are no other strong references but there are `Weak<T>`s, the inner value is moved into a new allocation
+    /// and the `Weak<T>`s are disassociated: they will no longer upgrade.
+    ///
+    /// # Examples
+    /// ```
+    /// use trc::Trc;
+    ///
+    /// let mut data = Trc::new(5);
+    ///
+    /// *Trc::make_mut(&mut data) += 1; // Won't clone anything
+    /// let mut other_data = Trc::clone(&data); // Won't clone inner data
+    /// *Trc::make_mut(&mut data) += 1; // Clones inner data
+    /// *Trc::make_mut(&mut data) += 1; // Won't clone anything
+    /// *Trc::make_mut(&mut other_data) *= 2; // Won't clone anything
+    ///
+    /// assert_eq!(*data, 8);
+    /// assert_eq!(*other_data, 12);
+    /// ```
+    ///
+    /// `Weak<T>`s are disassociated:
+    /// ```
+    /// use trc::Trc;
+    /// use trc::Weak;
+    ///
+    /// let mut data = Trc::new(75);
+    /// let weak = Trc::downgrade(&data);
+    ///
+    /// *Trc::make_mut(&mut data) += 1;
+    ///
+    /// assert_eq!(*data, 76);
+    /// assert!(Weak::upgrade(&weak).is_none());
+    /// ```
+    ///
+    /// Slices:
+    /// ```
+    /// use trc::Trc;
+    ///
+    /// let mut data: Trc<[i32]> = Trc::from(&[1, 2, 3][..]);
+    /// let other_data = Trc::clone(&data);
+    ///
+    /// Trc::make_mut(&mut data)[0] = 4; // Clones inner data
+    ///
+    /// assert_eq!(*data, [4, 2, 3]);
+    /// assert_eq!(*other_data, [1, 2, 3]);
+    /// ```
+    #[inline]
+    pub fn make_mut(this: &mut Self) -> &mut T {
+        //Taking the atomic reference count from 1 to 0 stops `Weak<T>`s from upgrading, like `Weak::upgrade` does
+        if *unsafe { this.threadref.as_ref() } != 1
+            || unsafe { this.shared.as_ref() }
+                .atomicref
+                .compare_exchange(
+                    1,
+                    0,
+                    core::sync::atomic::Ordering::Acquire,
+                    core::sync::atomic::Ordering::Relaxed,
+                )
+                .is_err()
+        {
+            //Other strong references exist, so clone the data
+            *this = T::clone_into_trc(this, &this.alloc);
+        } else if unsafe { this.shared.as_ref() }
+            .weakcount
+            .load(core::sync::atomic::Ordering::Relaxed)
+            != 1
+        {
+            //Only weak references remain, so move the data out and leave them a dropped value
+            let fresh = unsafe { T::move_into_trc(this, &this.alloc) };
+            let old = ManuallyDrop::new(core::mem::replace(this, fresh));
+
+            unsafe {
+                Trc::release_threadref(&old);
+
+                //Clean up implicit self-reference
+                drop(Weak {
+                    data: old.shared,
+                    alloc: ptr::read(&old.alloc),
+                });
+            }
+        } else {
+            //This was the only reference, so restore the atomic reference count
+            unsafe { this.shared.as_ref() }
+                .atomicref
+                .store(1, core::sync::atomic::Ordering::Release);
+        }
+
+        unsafe { &mut (*this.shared.as_ptr()).data }
+    }
+}
+
+impl<T: ?Sized, A: Allocator + Clone> Trc<T, A> {
     /// Create a `Weak<T>` from a `Trc<T>`. This increments the weak count.
     ///
     /// # Examples
@@ -1312,20 +2511,20 @@ impl<T: ?Sized> Trc<T> {
     /// let weak = Trc::downgrade(&trc);
     /// ```
     #[inline]
-    pub fn downgrade(trc: &Trc<T>) -> Weak<T> {
-        let prev = sum_value(
-            &unsafe { trc.shared.as_ref() }.weakcount,
-            1,
-            core::sync::atomic::Ordering::Acquire,
-        );
-        if prev > MAX_REFCOUNT {
-            panic!("Overflow of maximum weak reference count.");
+    pub fn downgrade(trc: &Trc<T, A>) -> Weak<T, A> {
+        increment_weakcount(&unsafe { trc.shared.as_ref() }.weakcount);
+
+        #[cfg(feature = "registry")]
+        Trc::register(trc);
+
+        Weak {
+            data: trc.shared,
+            alloc: trc.alloc.clone(),
         }
-        Weak { data: trc.shared }
     }
 }
 
-impl<T: ?Sized> Deref for Trc<T> {
+impl<T: ?Sized, A: Allocator> Deref for Trc<T, A> {
     type Target = T;
 
     /// Get an immutable reference to the internal data.
@@ -1345,12 +2544,12 @@ impl<T: ?Sized> Deref for Trc<T> {
     }
 }
 
-impl<T: ?Sized> Drop for Trc<T> {
+impl<T: ?Sized, A: Allocator> Drop for Trc<T, A> {
     #[inline]
     fn drop(&mut self) {
         *unsafe { self.threadref.as_mut() } -= 1;
         if *unsafe { self.threadref.as_ref() } == 0 {
-            drop(unsafe { Box::from_raw(self.threadref.as_ptr()) });
+            unsafe { Trc::release_threadref(self) };
             if sub_value(
                 &unsafe { self.shared.as_ref() }.atomicref,
                 1,
@@ -1361,13 +2560,25 @@ impl<T: ?Sized> Drop for Trc<T> {
             }
 
             core::sync::atomic::fence(core::sync::atomic::Ordering::Acquire);
+            #[cfg(feature = "reclaim")]
+            if unsafe { reclaim::release(self.shared, reclaim::Part::Value) } {
+                return;
+            }
+            #[cfg(feature = "home")]
+            if unsafe { home::release(self.shared) } {
+                return;
+            }
+
             unsafe { core::ptr::drop_in_place(addr_of_mut!((*self.shared.as_ptr()).data)) };
-            Weak { data: self.shared };
+            drop(Weak {
+                data: self.shared,
+                alloc: &self.alloc,
+            });
         }
     }
 }
 
-impl<T: ?Sized> Clone for Trc<T> {
+impl<T: ?Sized, A: Allocator + Clone> Clone for Trc<T, A> {
     /// Clone a `Trc<T>` (increment it's local reference count).
     /// It will panic if the local reference count overflows.
     /// ```
@@ -1387,41 +2598,42 @@ impl<T: ?Sized> Clone for Trc<T> {
         Trc {
             shared: self.shared,
             threadref: self.threadref,
+            alloc: self.alloc.clone(),
         }
     }
 }
 
-impl<T: ?Sized> AsRef<T> for Trc<T> {
+impl<T: ?Sized, A: Allocator> AsRef<T> for Trc<T, A> {
     fn as_ref(&self) -> &T {
         Trc::deref(self)
     }
 }
 
-impl<T: ?Sized> Borrow<T> for Trc<T> {
+impl<T: ?Sized, A: Allocator> Borrow<T> for Trc<T, A> {
     fn borrow(&self) -> &T {
         self.as_ref()
     }
 }
 
-impl<T: ?Sized + Default> Default for Trc<T> {
+impl<T: Default> Default for Trc<T> {
     fn default() -> Self {
         Trc::new(Default::default())
     }
 }
 
-impl<T: Display> Display for Trc<T> {
+impl<T: ?Sized + Display, A: Allocator> Display for Trc<T, A> {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         core::fmt::Display::fmt((*self).deref(), f)
     }
 }
 
-impl<T: Debug> Debug for Trc<T> {
+impl<T: ?Sized + Debug, A: Allocator> Debug for Trc<T, A> {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         core::fmt::Debug::fmt((*self).deref(), f)
     }
 }
 
-impl<T: ?Sized> Pointer for Trc<T> {
+impl<T: ?Sized, A: Allocator> Pointer for Trc<T, A> {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         core::fmt::Pointer::fmt(&addr_of!(unsafe { self.shared.as_ref() }.data), f)
     }
@@ -1442,7 +2654,7 @@ impl<T> From<T> for Trc<T> {
     }
 }
 
-impl<T: Hash> Hash for Trc<T> {
+impl<T: ?Sized + Hash, A: Allocator> Hash for Trc<T, A> {
     /// Pass the data contained in this `Trc<T>` to the provided hasher.
     #[inline]
     fn hash<H: Hasher>(&self, state: &mut H) {
@@ -1450,7 +2662,7 @@ impl<T: Hash> Hash for Trc<T> {
     }
 }
 
-impl<T: PartialOrd> PartialOrd for Trc<T> {
+impl<T: ?Sized + PartialOrd, A: Allocator> PartialOrd for Trc<T, A> {
     /// "Greater than or equal to" comparison for two `Trc<T>`s.
     ///
     /// Calls `.ge` on the data.
@@ -1482,7 +2694,7 @@ impl<T: PartialOrd> PartialOrd for Trc<T> {
     /// ```
     #[inline]
     fn le(&self, other: &Self) -> bool {
-        self.deref().ge(other.deref())
+        self.deref().le(other.deref())
     }
 
     /// "Greater than" comparison for two `Trc<T>`s.
@@ -1538,7 +2750,7 @@ impl<T: PartialOrd> PartialOrd for Trc<T> {
     }
 }
 
-impl<T: Ord> Ord for Trc<T> {
+impl<T: ?Sized + Ord, A: Allocator> Ord for Trc<T, A> {
     /// Comparison for two `Trc<T>`s. The two are compared by calling `.cmp` on the inner values.
     ///
     /// # Examples
@@ -1556,9 +2768,9 @@ impl<T: Ord> Ord for Trc<T> {
     }
 }
 
-impl<T: Eq> Eq for Trc<T> {}
+impl<T: ?Sized + Eq, A: Allocator> Eq for Trc<T, A> {}
 
-impl<T: PartialEq> PartialEq for Trc<T> {
+impl<T: ?Sized + PartialEq, A: Allocator> PartialEq for Trc<T, A> {
     /// Equality by value comparison for two `Trc<T>`s, even if the data is in different allocoations.
     ///
     /// Calls `.eq` on the data.
@@ -1595,20 +2807,20 @@ impl<T: PartialEq> PartialEq for Trc<T> {
     }
 }
 
-impl<T: AsFd> AsFd for Trc<T> {
+impl<T: AsFd, A: Allocator> AsFd for Trc<T, A> {
     fn as_fd(&self) -> std::os::fd::BorrowedFd<'_> {
         (**self).as_fd()
     }
 }
 
-impl<T: AsRawFd> AsRawFd for Trc<T> {
+impl<T: AsRawFd, A: Allocator> AsRawFd for Trc<T, A> {
     fn as_raw_fd(&self) -> std::os::fd::RawFd {
         (**self).as_raw_fd()
     }
 }
 
 #[allow(deprecated)]
-impl<T: Error> Error for Trc<T> {
+impl<T: ?Sized + Error, A: Allocator> Error for Trc<T, A> {
     fn cause(&self) -> Option<&dyn Error> {
         (**self).cause()
     }
@@ -1620,89 +2832,906 @@ impl<T: Error> Error for Trc<T> {
     }
 }
 
-impl<T: ?Sized> Unpin for Trc<T> {}
-impl<T: ?Sized> UnwindSafe for Trc<T> {}
+impl<T: ?Sized, A: Allocator> Unpin for Trc<T, A> {}
+impl<T: ?Sized, A: Allocator + UnwindSafe> UnwindSafe for Trc<T, A> {}
+
+/// Allocate a `SharedTrcInternal<[T]>` holding the elements of an iterator with an exact size. Panics if the iterator
+/// yields a different number of elements than its `len`. If the iterator panics, the allocation is not leaked.
+fn create_from_iterator_exact<T, A: Allocator>(
+    iterator: impl ExactSizeIterator<Item = T>,
+    alloc: &A,
+) -> NonNull<SharedTrcInternal<[T]>> {
+    let mut builder = SliceBuilder::new(iterator.len(), alloc);
+    builder.extend(iterator);
+    builder.finish()
+}
 
-fn create_from_iterator_exact<T>(
-    iterator: impl Iterator<Item = T> + ExactSizeIterator,
-) -> *mut SharedTrcInternal<[T]> {
-    let value_layout = Layout::array::<T>(iterator.len()).unwrap();
-    let layout = Layout::new::<SharedTrcInternal<()>>()
-        .extend(value_layout)
-        .unwrap()
-        .0
-        .pad_to_align();
+/// Data of a `SharedTrcInternal` which ends with a slice, such as `[T]` itself, and which can be written by a
+/// `SliceBuilder`.
+trait SliceData {
+    /// Type of the elements of the slice.
+    type Elem;
 
-    let res = slice_from_raw_parts_mut(unsafe { alloc(layout) } as *mut T, iterator.len())
-        as *mut SharedTrcInternal<[T]>;
-    unsafe { write(&mut (*res).atomicref, AtomicUsize::new(1)) };
-    unsafe { write(&mut (*res).weakcount, AtomicUsize::new(1)) };
+    /// Get a pointer to the first element of the slice.
+    ///
+    /// # Safety
+    /// `ptr` must point to an allocation for the data.
+    unsafe fn elems(ptr: *mut Self) -> *mut Self::Elem;
 
-    let elems = unsafe { addr_of_mut!((*res).data) } as *mut T;
-    for (n, i) in iterator.enumerate() {
-        unsafe { write(elems.add(n), i) };
-    }
-    res
+    /// Drop the fields which come before the slice.
+    ///
+    /// # Safety
+    /// `ptr` must point to an allocation for the data, whose fields before the slice are initialized.
+    unsafe fn drop_header(ptr: *mut Self);
 }
 
-trait TrcFromIter<T> {
-    fn from_iter(slice: impl Iterator<Item = T> + ExactSizeIterator) -> Self;
+impl<T> SliceData for [T] {
+    type Elem = T;
+
+    #[inline]
+    unsafe fn elems(ptr: *mut Self) -> *mut T {
+        ptr as *mut T
+    }
+
+    #[inline]
+    unsafe fn drop_header(_ptr: *mut Self) {}
+}
+
+/// Writes elements into a newly allocated `SharedTrcInternal<S>` whose data ends with a slice. If the builder is
+/// dropped before `finish`, for example because producing an element panicked, the written elements and the fields
+/// before the slice are dropped and the allocation is freed.
+struct SliceBuilder<'a, S: ?Sized + SliceData, A: Allocator> {
+    shared: NonNull<SharedTrcInternal<S>>,
+    layout: Layout,
+    len: usize,
+    written: usize,
+    alloc: &'a A,
+}
+
+impl<'a, T, A: Allocator> SliceBuilder<'a, [T], A> {
+    /// Allocate a `SharedTrcInternal<[T]>` for `len` elements.
+    #[inline]
+    fn new(len: usize, alloc: &'a A) -> Self {
+        let shared = allocate_for_slice::<T, A>(len, alloc);
+        unsafe {
+            SliceBuilder::from_allocation(shared, slice_layout::<T>(len).unwrap(), len, alloc)
+        }
+    }
+
+    /// Allocate a `SharedTrcInternal<[T]>` for `len` elements, returning an [`AllocError`] on failure.
+    #[inline]
+    fn try_new(len: usize, alloc: &'a A) -> Result<Self, AllocError> {
+        let shared = try_allocate_for_slice::<T, A>(len, alloc)?;
+        Ok(unsafe {
+            SliceBuilder::from_allocation(shared, slice_layout::<T>(len).unwrap(), len, alloc)
+        })
+    }
+
+    /// Write the next element, growing the allocation geometrically if it is full.
+    #[inline]
+    #[cfg(feature = "serde")]
+    fn push_grow(&mut self, elem: T) {
+        if self.written == self.len {
+            self.resize(self.len.saturating_mul(2).max(4));
+        }
+        self.push(elem);
+    }
+
+    /// Shrink the allocation to the written elements, and return it.
+    #[inline]
+    #[cfg(feature = "serde")]
+    fn finish_shrink(mut self) -> NonNull<SharedTrcInternal<[T]>> {
+        if self.written != self.len {
+            self.resize(self.written);
+        }
+        self.finish()
+    }
+
+    /// Reallocate the allocation with room for `len` elements, keeping the written ones. Panics if the layout
+    /// overflows, and calls [`handle_alloc_error`] if the reallocation fails.
+    #[cfg(feature = "serde")]
+    fn resize(&mut self, len: usize) {
+        debug_assert!(len >= self.written);
+        let layout = slice_layout::<T>(len).expect("Overflow of maximum slice length.");
+
+        let res = unsafe {
+            if layout.size() >= self.layout.size() {
+                self.alloc.grow(self.shared.cast(), self.layout, layout)
+            } else {
+                self.alloc.shrink(self.shared.cast(), self.layout, layout)
+            }
+        };
+        let ptr = match res {
+            Ok(ptr) => ptr,
+            Err(_) => handle_alloc_error(layout),
+        };
+
+        self.shared = unsafe {
+            NonNull::new_unchecked(slice_from_raw_parts_mut(ptr.as_ptr() as *mut T, len)
+                as *mut SharedTrcInternal<[T]>)
+        };
+        self.layout = layout;
+        self.len = len;
+    }
+}
+
+impl<'a, S: ?Sized + SliceData, A: Allocator> SliceBuilder<'a, S, A> {
+    /// Take ownership of an allocation whose slice has room for `len` elements.
+    ///
+    /// # Safety
+    /// `shared` must have been allocated with `alloc` and `layout`. Its fields before the slice must be initialized,
+    /// and none of its elements.
+    #[inline]
+    unsafe fn from_allocation(
+        shared: NonNull<SharedTrcInternal<S>>,
+        layout: Layout,
+        len: usize,
+        alloc: &'a A,
+    ) -> Self {
+        SliceBuilder {
+            shared,
+            layout,
+            len,
+            written: 0,
+            alloc,
+        }
+    }
+
+    /// Write the next element. Panics if all elements have already been written.
+    #[inline]
+    fn push(&mut self, elem: S::Elem) {
+        assert!(
+            self.written < self.len,
+            "Iterator yielded more elements than it reported."
+        );
+        unsafe {
+            let elems = S::elems(addr_of_mut!((*self.shared.as_ptr()).data));
+            write(elems.add(self.written), elem);
+        }
+        self.written += 1;
+    }
+
+    /// Write all elements of the iterator. Panics if it yields too many elements.
+    #[inline]
+    fn extend(&mut self, iter: impl Iterator<Item = S::Elem>) {
+        for elem in iter {
+            self.push(elem);
+        }
+    }
+
+    /// Return the allocation, with all of its elements written. Panics if fewer elements were written.
+    #[inline]
+    fn finish(self) -> NonNull<SharedTrcInternal<S>> {
+        assert_eq!(
+            self.written, self.len,
+            "Iterator yielded fewer elements than it reported."
+        );
+        ManuallyDrop::new(self).shared
+    }
+}
+
+impl<S: ?Sized + SliceData, A: Allocator> Drop for SliceBuilder<'_, S, A> {
+    fn drop(&mut self) {
+        unsafe {
+            let data = addr_of_mut!((*self.shared.as_ptr()).data);
+            S::drop_header(data);
+            ptr::drop_in_place(slice_from_raw_parts_mut(S::elems(data), self.written));
+
+            self.alloc.deallocate(self.shared.cast(), self.layout);
+        }
+    }
+}
+
+impl<T: Clone> From<&[T]> for Trc<[T]> {
+    /// From conversion from a reference to a slice of type `T` (`&[T]`) to a `Trc<[T]>`.
+    ///
+    /// # Examples
+    /// ```
+    /// use trc::Trc;
+    ///
+    /// let vec = (1..100).collect::<Vec<i32>>();
+    /// let slice = &vec[2..5];
+    /// let trc = Trc::<[i32]>::from(slice);
+    /// assert_eq!(&*trc, slice);
+    /// ```
+    fn from(value: &[T]) -> Trc<[T]> {
+        let shared = create_from_iterator_exact(value.iter().cloned(), &Global);
+
+        unsafe { Trc::from_inner_inline(shared, Global) }
+    }
+}
+
+/// Allocate a `SharedTrcInternal<[T]>` holding a bitwise copy of `slice`, with its counts initialized for a new `Trc<[T]>`.
+fn copy_from_slice<T: Copy, A: Allocator>(
+    slice: &[T],
+    alloc: &A,
+) -> NonNull<SharedTrcInternal<[T]>> {
+    let res = allocate_for_slice::<T, A>(slice.len(), alloc);
+    unsafe {
+        ptr::copy_nonoverlapping(
+            slice.as_ptr(),
+            addr_of_mut!((*res.as_ptr()).data) as *mut T,
+            slice.len(),
+        )
+    };
+    res
+}
+
+impl From<&str> for Trc<str> {
+    /// Create a new `Trc<str>` from a string slice, copying it into a single allocation.
+    ///
+    /// # Examples
+    /// ```
+    /// use trc::Trc;
+    ///
+    /// let trc = Trc::<str>::from("Trc");
+    /// assert_eq!(&*trc, "Trc");
+    /// ```
+    fn from(value: &str) -> Self {
+        let shared = copy_from_slice(value.as_bytes(), &Global);
+
+        unsafe {
+            Trc::from_inner_inline(
+                NonNull::new_unchecked(shared.as_ptr() as *mut SharedTrcInternal<str>),
+                Global,
+            )
+        }
+    }
+}
+
+impl From<String> for Trc<str> {
+    /// Create a new `Trc<str>` from a `String`, copying it into a single allocation.
+    ///
+    /// # Examples
+    /// ```
+    /// use trc::Trc;
+    ///
+    /// let trc = Trc::<str>::from(String::from("Trc"));
+    /// assert_eq!(&*trc, "Trc");
+    /// ```
+    fn from(value: String) -> Self {
+        Trc::from(&value[..])
+    }
+}
+
+impl Default for Trc<str> {
+    fn default() -> Self {
+        Trc::from("")
+    }
+}
+
+impl<A: Allocator> From<Trc<str, A>> for Trc<[u8], A> {
+    /// Convert a `Trc<str>` into a `Trc<[u8]>` holding its UTF-8 bytes, without reallocating.
+    ///
+    /// # Examples
+    /// ```
+    /// use trc::Trc;
+    ///
+    /// let bytes = Trc::<[u8]>::from(Trc::<str>::from("Trc"));
+    /// assert_eq!(&*bytes, b"Trc");
+    /// ```
+    fn from(value: Trc<str, A>) -> Self {
+        let value = ManuallyDrop::new(value);
+        Trc {
+            shared: unsafe {
+                NonNull::new_unchecked(value.shared.as_ptr() as *mut SharedTrcInternal<[u8]>)
+            },
+            threadref: value.threadref,
+            alloc: unsafe { ptr::read(&value.alloc) },
+        }
+    }
+}
+
+impl<A: Allocator> TryFrom<Trc<[u8], A>> for Trc<str, A> {
+    type Error = Utf8Error;
+
+    /// Convert a `Trc<[u8]>` into a `Trc<str>` without reallocating, if it holds valid UTF-8.
+    ///
+    /// # Examples
+    /// ```
+    /// use trc::Trc;
+    ///
+    /// let bytes = Trc::<[u8]>::from(&b"Trc"[..]);
+    /// let trc = Trc::<str>::try_from(bytes).unwrap();
+    /// assert_eq!(&*trc, "Trc");
+    ///
+    /// let bytes = Trc::<[u8]>::from(&[0xff][..]);
+    /// assert!(Trc::<str>::try_from(bytes).is_err());
+    /// ```
+    fn try_from(value: Trc<[u8], A>) -> Result<Self, Self::Error> {
+        std::str::from_utf8(&value)?;
+
+        let value = ManuallyDrop::new(value);
+        Ok(Trc {
+            shared: unsafe {
+                NonNull::new_unchecked(value.shared.as_ptr() as *mut SharedTrcInternal<str>)
+            },
+            threadref: value.threadref,
+            alloc: unsafe { ptr::read(&value.alloc) },
+        })
+    }
+}
+
+impl From<&str> for SharedTrc<str> {
+    /// Create a new `SharedTrc<str>` from a string slice, copying it into a single allocation.
+    ///
+    /// # Examples
+    /// ```
+    /// use trc::SharedTrc;
+    ///
+    /// let shared = SharedTrc::<str>::from("Trc");
+    /// assert_eq!(&*shared, "Trc");
+    /// ```
+    fn from(value: &str) -> Self {
+        SharedTrc::from(Trc::from(value))
+    }
+}
+
+impl From<String> for SharedTrc<str> {
+    /// Create a new `SharedTrc<str>` from a `String`, copying it into a single allocation.
+    ///
+    /// # Examples
+    /// ```
+    /// use trc::SharedTrc;
+    ///
+    /// let shared = SharedTrc::<str>::from(String::from("Trc"));
+    /// assert_eq!(&*shared, "Trc");
+    /// ```
+    fn from(value: String) -> Self {
+        SharedTrc::from(Trc::from(value))
+    }
+}
+
+impl From<Box<str>> for SharedTrc<str> {
+    /// Create a new `SharedTrc<str>` from a `Box<str>`, copying it into a single allocation.
+    ///
+    /// # Examples
+    /// ```
+    /// use trc::SharedTrc;
+    ///
+    /// let shared = SharedTrc::<str>::from(Box::<str>::from("Trc"));
+    /// assert_eq!(&*shared, "Trc");
+    /// ```
+    fn from(value: Box<str>) -> Self {
+        SharedTrc::from(Trc::from(value))
+    }
+}
+
+impl Default for SharedTrc<str> {
+    fn default() -> Self {
+        SharedTrc::from("")
+    }
+}
+
+impl<T: ?Sized> From<Box<T>> for Trc<T> {
+    /// Move a boxed value, which may be unsized, into a new `Trc<T>`. The box's allocation is freed without dropping
+    /// the value.
+    ///
+    /// # Examples
+    /// ```
+    /// use trc::Trc;
+    /// use std::fmt::Display;
+    ///
+    /// let boxed: Box<dyn Display> = Box::new(100);
+    /// let trc = Trc::<dyn Display>::from(boxed);
+    /// assert_eq!(trc.to_string(), "100");
+    ///
+    /// let trc = Trc::<[i32]>::from(vec![1, 2, 3].into_boxed_slice());
+    /// assert_eq!(*trc, [1, 2, 3]);
+    /// ```
+    fn from(value: Box<T>) -> Self {
+        let value_layout = Layout::for_value::<T>(&value);
+        let layout = inner_layout(value_layout).unwrap().0;
+        let mem = allocate_for_layout(layout, &Global);
+
+        let boxed = Box::into_raw(value);
+        unsafe {
+            let res = set_data_ptr(boxed as *mut SharedTrcInternal<T>, mem.as_ptr());
+            write_counts(res, 1);
+            ptr::copy_nonoverlapping(
+                boxed as *const u8,
+                addr_of_mut!((*res).data) as *mut u8,
+                value_layout.size(),
+            );
+
+            //Free the box's allocation without dropping the value, which was moved
+            drop(Box::from_raw(boxed as *mut ManuallyDrop<T>));
+
+            Trc::from_inner_inline(NonNull::new_unchecked(res), Global)
+        }
+    }
+}
+
+impl<T> From<Vec<T>> for Trc<[T]> {
+    /// Move the elements of a `Vec<T>` into a new `Trc<[T]>`, without cloning them.
+    ///
+    /// # Examples
+    /// ```
+    /// use trc::Trc;
+    ///
+    /// let trc = Trc::<[String]>::from(vec![String::from("a"), String::from("b")]);
+    /// assert_eq!(*trc, ["a", "b"]);
+    /// ```
+    fn from(mut value: Vec<T>) -> Self {
+        let shared = allocate_for_slice::<T, Global>(value.len(), &Global);
+        unsafe {
+            ptr::copy_nonoverlapping(
+                value.as_ptr(),
+                addr_of_mut!((*shared.as_ptr()).data) as *mut T,
+                value.len(),
+            );
+
+            //The elements were moved, so only free the vector's buffer
+            value.set_len(0);
+
+            Trc::from_inner_inline(shared, Global)
+        }
+    }
+}
+
+impl<'a, B> From<Cow<'a, B>> for Trc<B>
+where
+    B: ToOwned + ?Sized,
+    Trc<B>: From<&'a B> + From<B::Owned>,
+{
+    /// Create a new `Trc<B>` from a clone-on-write pointer, by copying its borrowed contents or moving its owned
+    /// contents.
+    ///
+    /// # Examples
+    /// ```
+    /// use trc::Trc;
+    /// use std::borrow::Cow;
+    ///
+    /// let cow: Cow<str> = Cow::Borrowed("Trc");
+    /// let trc = Trc::<str>::from(cow);
+    /// assert_eq!(&*trc, "Trc");
+    /// ```
+    fn from(value: Cow<'a, B>) -> Self {
+        match value {
+            Cow::Borrowed(borrowed) => Trc::from(borrowed),
+            Cow::Owned(owned) => Trc::from(owned),
+        }
+    }
+}
+
+//The layouts of `CStr`, `Path` and `OsStr` are private, so they are constructed from their boxed forms
+
+impl From<&CStr> for Trc<CStr> {
+    /// Create a new `Trc<CStr>` by copying the provided C string.
+    ///
+    /// # Examples
+    /// ```
+    /// use trc::Trc;
+    /// use std::ffi::CStr;
+    ///
+    /// let cstr = CStr::from_bytes_with_nul(b"Trc\0").unwrap();
+    /// let trc = Trc::<CStr>::from(cstr);
+    /// assert_eq!(&*trc, cstr);
+    /// ```
+    fn from(value: &CStr) -> Self {
+        Trc::from(Box::<CStr>::from(value))
+    }
+}
+
+impl From<CString> for Trc<CStr> {
+    /// Create a new `Trc<CStr>` from a `CString`.
+    ///
+    /// # Examples
+    /// ```
+    /// use trc::Trc;
+    /// use std::ffi::{CStr, CString};
+    ///
+    /// let trc = Trc::<CStr>::from(CString::new("Trc").unwrap());
+    /// assert_eq!(trc.to_bytes(), b"Trc");
+    /// ```
+    fn from(value: CString) -> Self {
+        Trc::from(value.into_boxed_c_str())
+    }
+}
+
+impl From<&Path> for Trc<Path> {
+    /// Create a new `Trc<Path>` by copying the provided path.
+    ///
+    /// # Examples
+    /// ```
+    /// use trc::Trc;
+    /// use std::path::Path;
+    ///
+    /// let trc = Trc::<Path>::from(Path::new("/tmp/trc"));
+    /// assert_eq!(&*trc, Path::new("/tmp/trc"));
+    /// ```
+    fn from(value: &Path) -> Self {
+        Trc::from(Box::<Path>::from(value))
+    }
+}
+
+impl From<PathBuf> for Trc<Path> {
+    /// Create a new `Trc<Path>` from a `PathBuf`.
+    ///
+    /// # Examples
+    /// ```
+    /// use trc::Trc;
+    /// use std::path::{Path, PathBuf};
+    ///
+    /// let trc = Trc::<Path>::from(PathBuf::from("/tmp/trc"));
+    /// assert_eq!(&*trc, Path::new("/tmp/trc"));
+    /// ```
+    fn from(value: PathBuf) -> Self {
+        Trc::from(value.into_boxed_path())
+    }
+}
+
+impl From<&OsStr> for Trc<OsStr> {
+    /// Create a new `Trc<OsStr>` by copying the provided OS string.
+    ///
+    /// # Examples
+    /// ```
+    /// use trc::Trc;
+    /// use std::ffi::OsStr;
+    ///
+    /// let trc = Trc::<OsStr>::from(OsStr::new("Trc"));
+    /// assert_eq!(&*trc, "Trc");
+    /// ```
+    fn from(value: &OsStr) -> Self {
+        Trc::from(Box::<OsStr>::from(value))
+    }
+}
+
+impl From<OsString> for Trc<OsStr> {
+    /// Create a new `Trc<OsStr>` from an `OsString`.
+    ///
+    /// # Examples
+    /// ```
+    /// use trc::Trc;
+    /// use std::ffi::{OsStr, OsString};
+    ///
+    /// let trc = Trc::<OsStr>::from(OsString::from("Trc"));
+    /// assert_eq!(&*trc, "Trc");
+    /// ```
+    fn from(value: OsString) -> Self {
+        Trc::from(value.into_boxed_os_str())
+    }
+}
+
+impl<T: Clone> From<Arc<T>> for Trc<T> {
+    /// Create a new `Trc<T>` from an `Arc<T>`. If the `Arc<T>` is the only strong reference, the value is moved out of
+    /// it. Otherwise, it is cloned.
+    ///
+    /// # Examples
+    /// ```
+    /// use trc::Trc;
+    /// use std::sync::Arc;
+    ///
+    /// let trc: Trc<i32> = Trc::from(Arc::new(100));
+    /// assert_eq!(*trc, 100);
+    /// ```
+    fn from(value: Arc<T>) -> Self {
+        Trc::new(Arc::try_unwrap(value).unwrap_or_else(|arc| (*arc).clone()))
+    }
+}
+
+impl<T> FromIterator<T> for Trc<[T]> {
+    /// From conversion from an iterator (`impl IntoIterator<Item = T>`) to `Trc<[T]>`. If the iterator's size hint is
+    /// exact, the elements are written directly into a single allocation. Otherwise, they are collected into a `Vec<T>`
+    /// first and then moved.
+    ///
+    /// If the iterator panics, the elements which were already written are dropped and the allocation is freed.
+    ///
+    /// # Examples
+    /// ```
+    /// use trc::Trc;
+    ///
+    /// let trc = Trc::<[i32]>::from_iter(vec![1,2,3]);
+    /// assert_eq!(&*trc, vec![1,2,3]);
+    /// ```
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let iter = iter.into_iter();
+        match iter.size_hint() {
+            (lower, Some(upper)) if lower == upper => {
+                let mut builder = SliceBuilder::new(lower, &Global);
+                builder.extend(iter);
+                unsafe { Trc::from_inner_inline(builder.finish(), Global) }
+            }
+            _ => Self::from(iter.collect::<Vec<_>>()),
+        }
+    }
+}
+
+/// Converts a `Trc<T>`, `SharedTrc<T>` or `Weak<T>` into one pointing to an unsized type `U`, such as a trait object
+/// or a slice, which `T` can be coerced to. This reuses the existing allocation and keeps all reference counts.
+/// With the `nightly` feature, this coercion can also happen implicitly.
+///
+/// # Examples
+/// ```
+/// use trc::{coerce_trc, SharedTrc, Trc, Weak};
+///
+/// trait Vehicle {
+///     fn wheels(&self) -> usize;
+/// }
+///
+/// struct Truck;
+///
+/// impl Vehicle for Truck {
+///     fn wheels(&self) -> usize {
+///         18
+///     }
+/// }
+///
+/// let truck = Trc::new(Truck);
+/// let weak = coerce_trc!(Trc::downgrade(&truck) => dyn Vehicle);
+/// let shared = coerce_trc!(SharedTrc::from_trc(&truck) => dyn Vehicle);
+/// let vehicle: Trc<dyn Vehicle> = coerce_trc!(truck => dyn Vehicle);
+///
+/// assert_eq!(vehicle.wheels(), 18);
+/// assert_eq!(shared.wheels(), 18);
+/// assert_eq!(Weak::upgrade(&weak).unwrap().wheels(), 18);
+///
+/// let slice = coerce_trc!(Trc::new([1, 2, 3]) => [i32]);
+/// assert_eq!(*slice, [1, 2, 3]);
+/// ```
+#[macro_export]
+macro_rules! coerce_trc {
+    ($value:expr => $target:ty) => {
+        match $value {
+            value => unsafe { $crate::CoerceTrc::coerce(value, |ptr| -> *const $target { ptr }) },
+        }
+    };
+}
+
+/// Implementation detail of [`coerce_trc!`].
+#[doc(hidden)]
+pub trait CoerceTrc<T> {
+    type Output<U: ?Sized>;
+
+    /// # Safety
+    /// `coerce` must return its argument, only changing its type.
+    unsafe fn coerce<U: ?Sized>(self, coerce: impl FnOnce(*const T) -> *const U)
+        -> Self::Output<U>;
+}
+
+/// Coerce the pointer to a `SharedTrcInternal<T>`. Coercing `*const T` to `*const U` only attaches the metadata of `U`
+/// and keeps the address, so it is applied directly to the address of the `SharedTrcInternal<T>`.
+///
+/// # Safety
+/// `coerce` must return its argument, only changing its type.
+#[inline]
+unsafe fn coerce_inner<T, U: ?Sized>(
+    ptr: NonNull<SharedTrcInternal<T>>,
+    coerce: impl FnOnce(*const T) -> *const U,
+) -> NonNull<SharedTrcInternal<U>> {
+    NonNull::new_unchecked(coerce(ptr.as_ptr() as *const T) as *mut SharedTrcInternal<U>)
+}
+
+impl<T, A: Allocator> CoerceTrc<T> for Trc<T, A> {
+    type Output<U: ?Sized> = Trc<U, A>;
+
+    unsafe fn coerce<U: ?Sized>(self, coerce: impl FnOnce(*const T) -> *const U) -> Trc<U, A> {
+        let this = ManuallyDrop::new(self);
+        Trc {
+            shared: coerce_inner(this.shared, coerce),
+            threadref: this.threadref,
+            alloc: ptr::read(&this.alloc),
+        }
+    }
+}
+
+impl<T, A: Allocator> CoerceTrc<T> for SharedTrc<T, A> {
+    type Output<U: ?Sized> = SharedTrc<U, A>;
+
+    unsafe fn coerce<U: ?Sized>(
+        self,
+        coerce: impl FnOnce(*const T) -> *const U,
+    ) -> SharedTrc<U, A> {
+        let this = ManuallyDrop::new(self);
+        SharedTrc {
+            data: coerce_inner(this.data, coerce),
+            alloc: ptr::read(&this.alloc),
+        }
+    }
+}
+
+impl<T, A: Allocator> CoerceTrc<T> for Weak<T, A> {
+    type Output<U: ?Sized> = Weak<U, A>;
+
+    unsafe fn coerce<U: ?Sized>(self, coerce: impl FnOnce(*const T) -> *const U) -> Weak<U, A> {
+        let this = ManuallyDrop::new(self);
+        Weak {
+            data: coerce_inner(this.data, coerce),
+            alloc: ptr::read(&this.alloc),
+        }
+    }
 }
 
-impl<T: Clone + ?Sized> TrcFromIter<T> for Trc<[T]> {
-    fn from_iter(slice: impl Iterator<Item = T> + ExactSizeIterator) -> Self {
-        let shared = create_from_iterator_exact(slice);
-        let tbx = Box::new(1);
+#[cfg(feature = "nightly")]
+impl<T: ?Sized + std::marker::Unsize<U>, U: ?Sized, A: Allocator> std::ops::CoerceUnsized<Trc<U, A>>
+    for Trc<T, A>
+{
+}
 
-        Trc {
-            threadref: NonNull::from(Box::leak(tbx)),
-            shared: unsafe { NonNull::new_unchecked(shared) },
+#[cfg(feature = "nightly")]
+impl<T: ?Sized + std::marker::Unsize<U>, U: ?Sized, A: Allocator>
+    std::ops::CoerceUnsized<SharedTrc<U, A>> for SharedTrc<T, A>
+{
+}
+
+#[cfg(feature = "nightly")]
+impl<T: ?Sized + std::marker::Unsize<U>, U: ?Sized, A: Allocator>
+    std::ops::CoerceUnsized<Weak<U, A>> for Weak<T, A>
+{
+}
+
+impl<A: Allocator> Trc<dyn Any, A> {
+    /// Attempt to downcast the `Trc<dyn Any>` to a concrete type. The local and atomic reference counts are kept.
+    ///
+    /// # Examples
+    /// ```
+    /// use std::any::Any;
+    /// use trc::{coerce_trc, Trc};
+    ///
+    /// let any = coerce_trc!(Trc::new(100i32) => dyn Any);
+    /// let Err(any) = any.downcast::<String>() else {
+    ///     unreachable!()
+    /// };
+    /// assert_eq!(*any.downcast::<i32>().ok().unwrap(), 100);
+    /// ```
+    #[inline]
+    pub fn downcast<T: Any>(self) -> Result<Trc<T, A>, Self> {
+        if (*self).is::<T>() {
+            let this = ManuallyDrop::new(self);
+            Ok(Trc {
+                shared: this.shared.cast(),
+                threadref: this.threadref,
+                alloc: unsafe { ptr::read(&this.alloc) },
+            })
+        } else {
+            Err(self)
         }
     }
 }
 
-impl<T: Clone + ?Sized> From<&[T]> for Trc<[T]> {
-    /// From conversion from a reference to a slice of type `T` (`&[T]`) to a `Trc<[T]>`.
+impl<A: Allocator> Trc<dyn Any + Send + Sync, A> {
+    /// Attempt to downcast the `Trc<dyn Any + Send + Sync>` to a concrete type. The local and atomic reference counts
+    /// are kept.
     ///
     /// # Examples
     /// ```
-    /// use trc::Trc;
+    /// use std::any::Any;
+    /// use trc::{coerce_trc, Trc};
     ///
-    /// let vec = (1..100).collect::<Vec<i32>>();
-    /// let slice = &vec[2..5];
-    /// let trc = Trc::<[i32]>::from(slice);
-    /// assert_eq!(&*trc, slice);
+    /// let any = coerce_trc!(Trc::new(100i32) => dyn Any + Send + Sync);
+    /// assert_eq!(*any.downcast::<i32>().ok().unwrap(), 100);
     /// ```
-    fn from(value: &[T]) -> Trc<[T]> {
-        <Self as TrcFromIter<T>>::from_iter(value.iter().cloned())
+    #[inline]
+    pub fn downcast<T: Any + Send + Sync>(self) -> Result<Trc<T, A>, Self> {
+        if (*self).is::<T>() {
+            let this = ManuallyDrop::new(self);
+            Ok(Trc {
+                shared: this.shared.cast(),
+                threadref: this.threadref,
+                alloc: unsafe { ptr::read(&this.alloc) },
+            })
+        } else {
+            Err(self)
+        }
     }
 }
 
-impl<T: Clone + ?Sized> FromIterator<T> for Trc<[T]> {
-    /// From conversion from an iterator (`impl IntoIterator<Item = T>`) to `Trc<[T]>`. Due to Rust's unstable trait specialization feature,
-    /// there is no special case for iterators that implement [`ExactSizeIterator`].
+impl<A: Allocator> SharedTrc<dyn Any, A> {
+    /// Attempt to downcast the `SharedTrc<dyn Any>` to a concrete type. The atomic reference count is kept.
     ///
     /// # Examples
     /// ```
-    /// use trc::Trc;
+    /// use std::any::Any;
+    /// use trc::{coerce_trc, SharedTrc, Trc};
     ///
-    /// let trc = Trc::<[i32]>::from_iter(vec![1,2,3]);
-    /// assert_eq!(&*trc, vec![1,2,3]);
+    /// let any = coerce_trc!(SharedTrc::from(Trc::new(100i32)) => dyn Any);
+    /// assert_eq!(*any.downcast::<i32>().ok().unwrap(), 100);
     /// ```
-    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
-        Self::from(&iter.into_iter().collect::<Vec<_>>()[..])
+    #[inline]
+    pub fn downcast<T: Any>(self) -> Result<SharedTrc<T, A>, Self> {
+        if (*self).is::<T>() {
+            let this = ManuallyDrop::new(self);
+            Ok(SharedTrc {
+                data: this.data.cast(),
+                alloc: unsafe { ptr::read(&this.alloc) },
+            })
+        } else {
+            Err(self)
+        }
     }
 }
 
-//TODO: Integration with standard library for both, or use lib & conditional for just CoerceUnsized
-//impl<T: ?Sized + std::marker::Unsize<U>, U: ?Sized> std::ops::CoerceUnsized<Trc<U>> for Trc<T> {}
-//impl<T: ?Sized> std::ops::Receiver for Trc<T> {}
+impl<A: Allocator> SharedTrc<dyn Any + Send + Sync, A> {
+    /// Attempt to downcast the `SharedTrc<dyn Any + Send + Sync>` to a concrete type. The atomic reference count is kept.
+    ///
+    /// # Examples
+    /// ```
+    /// use std::any::Any;
+    /// use trc::{coerce_trc, SharedTrc, Trc};
+    ///
+    /// let any = coerce_trc!(SharedTrc::from(Trc::new(100i32)) => dyn Any + Send + Sync);
+    /// let handle = std::thread::spawn(move || *any.downcast::<i32>().ok().unwrap());
+    /// assert_eq!(handle.join().unwrap(), 100);
+    /// ```
+    #[inline]
+    pub fn downcast<T: Any + Send + Sync>(self) -> Result<SharedTrc<T, A>, Self> {
+        if (*self).is::<T>() {
+            let this = ManuallyDrop::new(self);
+            Ok(SharedTrc {
+                data: this.data.cast(),
+                alloc: unsafe { ptr::read(&this.alloc) },
+            })
+        } else {
+            Err(self)
+        }
+    }
+}
 
+impl<A: Allocator + Clone> Weak<dyn Any, A> {
+    /// Attempt to downcast the `Weak<dyn Any>` to a concrete type. The weak count is kept.
+    /// The type can only be checked while the data is alive, so this returns an [`Err`] if it has been dropped.
+    ///
+    /// # Examples
+    /// ```
+    /// use std::any::Any;
+    /// use trc::{coerce_trc, Trc, Weak};
+    ///
+    /// let trc = Trc::new(100i32);
+    /// let any = coerce_trc!(Trc::downgrade(&trc) => dyn Any);
+    /// let weak = any.downcast::<i32>().ok().unwrap();
+    /// assert!(Trc::ptr_eq(&Weak::upgrade(&weak).unwrap(), &trc));
+    /// ```
+    #[inline]
+    pub fn downcast<T: Any>(self) -> Result<Weak<T, A>, Self> {
+        match Weak::upgrade_shared(&self) {
+            Some(shared) if (*shared).is::<T>() => {
+                let this = ManuallyDrop::new(self);
+                Ok(Weak {
+                    data: this.data.cast(),
+                    alloc: unsafe { ptr::read(&this.alloc) },
+                })
+            }
+            _ => Err(self),
+        }
+    }
+}
 
+impl<A: Allocator + Clone> Weak<dyn Any + Send + Sync, A> {
+    /// Attempt to downcast the `Weak<dyn Any + Send + Sync>` to a concrete type. The weak count is kept.
+    /// The type can only be checked while the data is alive, so this returns an [`Err`] if it has been dropped.
+    ///
+    /// # Examples
+    /// ```
+    /// use std::any::Any;
+    /// use trc::{coerce_trc, Trc, Weak};
+    ///
+    /// let trc = Trc::new(100i32);
+    /// let any = coerce_trc!(Trc::downgrade(&trc) => dyn Any + Send + Sync);
+    /// let weak = any.downcast::<i32>().ok().unwrap();
+    /// assert!(Trc::ptr_eq(&Weak::upgrade(&weak).unwrap(), &trc));
+    /// ```
+    #[inline]
+    pub fn downcast<T: Any + Send + Sync>(self) -> Result<Weak<T, A>, Self> {
+        match Weak::upgrade_shared(&self) {
+            Some(shared) if (*shared).is::<T>() => {
+                let this = ManuallyDrop::new(self);
+                Ok(Weak {
+                    data: this.data.cast(),
+                    alloc: unsafe { ptr::read(&this.alloc) },
+                })
+            }
+            _ => Err(self),
+        }
+    }
+}
 
-impl<T: ?Sized> Drop for Weak<T> {
+impl<T: ?Sized, A: Allocator> Drop for Weak<T, A> {
     #[inline]
     fn drop(&mut self) {
+        if is_dangling(self.data.as_ptr()) {
+            return;
+        }
+
         if sub_value(
             unsafe { &(*self.data.as_ptr()).weakcount },
             1,
@@ -1713,15 +3742,25 @@ impl<T: ?Sized> Drop for Weak<T> {
         }
 
         core::sync::atomic::fence(core::sync::atomic::Ordering::Acquire);
+        #[cfg(feature = "reclaim")]
+        if unsafe { reclaim::release(self.data, reclaim::Part::Allocation) } {
+            return;
+        }
+        #[cfg(feature = "home")]
+        unsafe {
+            home::release_allocation(self.data)
+        };
 
         let layout = Layout::for_value(unsafe { &*self.data.as_ptr() });
-        unsafe { std::alloc::dealloc(self.data.as_ptr().cast(), layout) };
+        unsafe { self.alloc.deallocate(self.data.cast(), layout) };
     }
 }
 
-impl<T: ?Sized> Weak<T> {
+impl<T: ?Sized, A: Allocator + Clone> Weak<T, A> {
     /// Create a `Trc<T>` from a `Weak<T>`. Because `Weak<T>` does not own the value, it might have been dropped already. If it has, a `None` is returned.
     /// If the value has not been dropped, then this function a) decrements the weak count, and b) increments the atomic reference count of the object.
+    /// With the `registry` feature, if this thread already has a `Trc<T>` pointing to the same data, the returned `Trc<T>`
+    /// shares its local thread reference count instead.
     ///
     /// # Examples
     /// ```
@@ -1735,7 +3774,48 @@ impl<T: ?Sized> Weak<T> {
     /// assert_eq!(*new_trc, 100i32);
     /// ```
     #[inline]
-    pub fn upgrade(this: &Self) -> Option<Trc<T>> {
+    pub fn upgrade(this: &Self) -> Option<Trc<T, A>> {
+        if is_dangling(this.data.as_ptr()) {
+            return None;
+        }
+
+        //If this thread already has a `Trc<T>`, the data is alive and the atomic reference count does not need to change
+        #[cfg(feature = "registry")]
+        if let Some(threadref) = unsafe { acquire_registered_threadref(this.data) } {
+            return Some(Trc {
+                shared: this.data,
+                threadref,
+                alloc: this.alloc.clone(),
+            });
+        }
+
+        Weak::upgrade_shared(this).map(Trc::from)
+    }
+
+    /// Create a `SharedTrc<T>` from a `Weak<T>`, without creating a `Trc<T>` or a local thread reference count.
+    /// Because `Weak<T>` does not own the value, it might have been dropped already. If it has, a `None` is returned.
+    ///
+    /// # Examples
+    /// ```
+    /// use trc::Trc;
+    /// use trc::SharedTrc;
+    /// use trc::Weak;
+    ///
+    /// let shared: SharedTrc<_> = Trc::new(100i32).into();
+    /// let weak = SharedTrc::downgrade(&shared);
+    /// let new_shared = Weak::upgrade_shared(&weak).expect("Value was dropped");
+    /// drop(shared);
+    /// assert_eq!(*new_shared, 100i32);
+    ///
+    /// drop(new_shared);
+    /// assert!(Weak::upgrade_shared(&weak).is_none());
+    /// ```
+    #[inline]
+    pub fn upgrade_shared(this: &Self) -> Option<SharedTrc<T, A>> {
+        if is_dangling(this.data.as_ptr()) {
+            return None;
+        }
+
         unsafe { this.data.as_ref() }
             .atomicref
             .fetch_update(
@@ -1755,17 +3835,14 @@ impl<T: ?Sized> Weak<T> {
                 },
             )
             .ok()
-            .map(|_| {
-                let tbx = Box::new(1);
-                Trc {
-                    threadref: NonNull::from(Box::leak(tbx)),
-                    shared: this.data,
-                }
+            .map(|_| SharedTrc {
+                data: this.data,
+                alloc: this.alloc.clone(),
             })
     }
 }
 
-impl<T: ?Sized> Clone for Weak<T> {
+impl<T: ?Sized, A: Allocator + Clone> Clone for Weak<T, A> {
     /// Clone a `Weak<T>` (increment the weak count).
     ///
     /// # Examples
@@ -1780,21 +3857,259 @@ impl<T: ?Sized> Clone for Weak<T> {
     /// ```
     #[inline]
     fn clone(&self) -> Self {
-        let prev = sum_value(
-            &unsafe { self.data.as_ref() }.weakcount,
-            1,
-            core::sync::atomic::Ordering::Relaxed,
-        );
+        if !is_dangling(self.data.as_ptr()) {
+            let prev = sum_value(
+                &unsafe { self.data.as_ref() }.weakcount,
+                1,
+                core::sync::atomic::Ordering::Relaxed,
+            );
 
-        //If an absurd number of threads are created, and then they are aborted before this, UB can
-        //occur if the refcount wraps around.
-        if prev > MAX_REFCOUNT {
-            panic!("Overflow of maximum weak reference count.");
+            //If an absurd number of threads are created, and then they are aborted before this, UB can
+            //occur if the refcount wraps around.
+            if prev > MAX_REFCOUNT {
+                panic!("Overflow of maximum weak reference count.");
+            }
+        }
+
+        Weak {
+            data: self.data,
+            alloc: self.alloc.clone(),
+        }
+    }
+}
+
+impl<T> Weak<T> {
+    /// Constructs a new `Weak<T>` without allocating any memory. Calling `Weak::upgrade` on it always returns [`None`].
+    ///
+    /// # Examples
+    /// ```
+    /// use trc::Weak;
+    ///
+    /// let weak: Weak<i32> = Weak::new();
+    /// assert!(Weak::upgrade(&weak).is_none());
+    /// ```
+    #[inline]
+    pub fn new() -> Self {
+        Self::new_in(Global)
+    }
+
+    /// Converts a `*const T` into `Weak<T>`. The caller must uphold the below safety constraints.
+    ///
+    /// # Safety
+    /// - The given pointer must have come from `Weak::into_raw`, and still own its weak reference.
+    /// - After `from_raw`, the pointer must not be accessed.
+    ///
+    /// # Examples
+    /// ```
+    /// use trc::Trc;
+    /// use trc::Weak;
+    ///
+    /// let trc = Trc::new(100);
+    /// let ptr = Weak::into_raw(Trc::downgrade(&trc));
+    ///
+    /// let weak = unsafe { Weak::from_raw(ptr) };
+    /// assert_eq!(*Weak::upgrade(&weak).unwrap(), 100);
+    /// ```
+    pub unsafe fn from_raw(ptr: *const T) -> Self {
+        Self::from_raw_in(ptr, Global)
+    }
+}
+
+impl<T> Default for Weak<T> {
+    /// Constructs a new `Weak<T>` without allocating any memory. See `Weak::new`.
+    fn default() -> Self {
+        Weak::new()
+    }
+}
+
+impl<T, A: Allocator> Weak<T, A> {
+    /// Constructs a new `Weak<T, A>` in the provided allocator, without allocating any memory.
+    /// Calling `Weak::upgrade` on it always returns [`None`].
+    ///
+    /// # Examples
+    /// ```
+    /// # #![cfg_attr(feature = "nightly", feature(allocator_api))]
+    /// use trc::Weak;
+    /// use allocator_api2::alloc::Global;
+    ///
+    /// let weak: Weak<i32> = Weak::new_in(Global);
+    /// assert!(Weak::upgrade(&weak).is_none());
+    /// ```
+    #[inline]
+    pub fn new_in(alloc: A) -> Self {
+        Weak {
+            data: unsafe {
+                NonNull::new_unchecked(
+                    ptr::null_mut::<u8>()
+                        .wrapping_sub(1)
+                        .cast::<SharedTrcInternal<T>>(),
+                )
+            },
+            alloc,
+        }
+    }
+
+    /// Gets the raw pointer to the data pointed to by this `Weak<T>`.
+    /// The pointer is only valid to dereference while there are strong references; it may be dangling or unaligned
+    /// otherwise, for example if the `Weak<T>` was created with `Weak::new`.
+    ///
+    /// # Examples
+    /// ```
+    /// use trc::Trc;
+    /// use trc::Weak;
+    ///
+    /// let trc = Trc::new(100);
+    /// let weak = Trc::downgrade(&trc);
+    /// assert!(std::ptr::eq(Weak::as_ptr(&weak), Trc::as_ptr(&trc)));
+    /// ```
+    #[inline]
+    pub fn as_ptr(this: &Self) -> *const T {
+        let sharedptr = NonNull::as_ptr(this.data);
+        if is_dangling(sharedptr) {
+            return sharedptr as *const T;
+        }
+        unsafe { addr_of_mut!((*sharedptr).data) }
+    }
+
+    /// Converts a `Weak<T>` into `*const T`, without decrementing the weak count.
+    /// To avoid a memory leak, be sure to call `from_raw` to reclaim the weak reference.
+    ///
+    /// # Examples
+    /// ```
+    /// use trc::Trc;
+    /// use trc::Weak;
+    ///
+    /// let trc = Trc::new(100);
+    /// let ptr = Weak::into_raw(Trc::downgrade(&trc));
+    ///
+    /// assert_eq!(unsafe { *ptr }, 100);
+    ///
+    /// unsafe { Weak::from_raw(ptr) };
+    /// ```
+    pub fn into_raw(this: Self) -> *const T {
+        Weak::into_raw_with_allocator(this).0
+    }
+
+    /// Converts a `Weak<T, A>` into `*const T` and its allocator, without decrementing the weak count.
+    /// To avoid a memory leak, be sure to call `from_raw_in` to reclaim the weak reference.
+    ///
+    /// # Examples
+    /// ```
+    /// use trc::Trc;
+    /// use trc::Weak;
+    ///
+    /// let trc = Trc::new(100);
+    /// let (ptr, alloc) = Weak::into_raw_with_allocator(Trc::downgrade(&trc));
+    ///
+    /// unsafe { Weak::from_raw_in(ptr, alloc) };
+    /// ```
+    pub fn into_raw_with_allocator(this: Self) -> (*const T, A) {
+        let this = ManuallyDrop::new(this);
+        let alloc = unsafe { ptr::read(&this.alloc) };
+        (Weak::as_ptr(&this), alloc)
+    }
+
+    /// Converts a `*const T` into `Weak<T, A>`, using the provided allocator. The caller must uphold the below safety
+    /// constraints.
+    ///
+    /// # Safety
+    /// - The given pointer must have come from `Weak::into_raw` or `Weak::into_raw_with_allocator`, and still own its
+    ///   weak reference.
+    /// - The allocator must be the one that the allocation was created with.
+    /// - After `from_raw_in`, the pointer must not be accessed.
+    ///
+    /// # Examples
+    /// ```
+    /// use trc::Weak;
+    ///
+    /// let (ptr, alloc) = Weak::<i32>::into_raw_with_allocator(Weak::new());
+    /// let weak = unsafe { Weak::from_raw_in(ptr, alloc) };
+    /// assert!(Weak::upgrade(&weak).is_none());
+    /// ```
+    pub unsafe fn from_raw_in(ptr: *const T, alloc: A) -> Self {
+        let data = if is_dangling(ptr) {
+            ptr as *mut SharedTrcInternal<T>
+        } else {
+            inner_from_data_ptr(ptr, core::mem::align_of::<T>())
+        };
+
+        Weak {
+            data: NonNull::new_unchecked(data),
+            alloc,
+        }
+    }
+}
+
+impl<T: ?Sized, A: Allocator> Weak<T, A> {
+    /// Return the atomic reference count of the object. This is how many threads and `SharedTrc<T>`s are keeping the data
+    /// alive, or 0 if the data has been dropped or the `Weak<T>` was created with `Weak::new`.
+    ///
+    /// # Examples
+    /// ```
+    /// use trc::Trc;
+    /// use trc::Weak;
+    ///
+    /// let trc = Trc::new(100);
+    /// let weak = Trc::downgrade(&trc);
+    /// assert_eq!(Weak::strong_count(&weak), 1);
+    ///
+    /// drop(trc);
+    /// assert_eq!(Weak::strong_count(&weak), 0);
+    /// ```
+    #[inline]
+    pub fn strong_count(this: &Self) -> usize {
+        if is_dangling(this.data.as_ptr()) {
+            return 0;
+        }
+        unsafe { this.data.as_ref() }
+            .atomicref
+            .load(core::sync::atomic::Ordering::Relaxed)
+    }
+
+    /// Return the weak count of the object, or 0 if the `Weak<T>` was created with `Weak::new`.
+    /// While the data is alive, this includes the implicit weak reference held by all `Trc<T>` to themselves.
+    ///
+    /// # Examples
+    /// ```
+    /// use trc::Trc;
+    /// use trc::Weak;
+    ///
+    /// let trc = Trc::new(100);
+    /// let weak = Trc::downgrade(&trc);
+    /// assert_eq!(Weak::weak_count(&weak), 2);
+    ///
+    /// drop(trc);
+    /// assert_eq!(Weak::weak_count(&weak), 1);
+    /// ```
+    #[inline]
+    pub fn weak_count(this: &Self) -> usize {
+        if is_dangling(this.data.as_ptr()) {
+            return 0;
         }
+        unsafe { this.data.as_ref() }
+            .weakcount
+            .load(core::sync::atomic::Ordering::Relaxed)
+    }
 
-        Weak { data: self.data }
+    /// Checks if the other `Weak<T>` points to the same allocation as this one. Two `Weak<T>`s created with `Weak::new`
+    /// are equal.
+    ///
+    /// # Examples
+    /// ```
+    /// use trc::Trc;
+    /// use trc::Weak;
+    ///
+    /// let trc = Trc::new(100);
+    /// let weak1 = Trc::downgrade(&trc);
+    /// let weak2 = Trc::downgrade(&trc);
+    /// assert!(Weak::ptr_eq(&weak1, &weak2));
+    /// assert!(!Weak::ptr_eq(&weak1, &Weak::new()));
+    /// ```
+    #[inline]
+    pub fn ptr_eq(this: &Self, other: &Self) -> bool {
+        this.data.cast::<u8>() == other.data.cast::<u8>()
     }
 }
 
-unsafe impl<T: Sync + Send> Send for Weak<T> {}
-unsafe impl<T: Sync + Send> Sync for Weak<T> {}
+unsafe impl<T: ?Sized + Sync + Send, A: Allocator + Send> Send for Weak<T, A> {}
+unsafe impl<T: ?Sized + Sync + Send, A: Allocator + Sync> Sync for Weak<T, A> {}
@@ -0,0 +1,63 @@
+//! Per-thread pool of local thread reference counts, enabled by the `pool` feature.
+//!
+//! A local thread reference count is only ever used by the thread which allocated it, because `Trc<T>` is not
+//! [`Send`]. Freed counts are kept on a per-thread free list and handed out again, instead of going through the global
+//! allocator every time a `Trc<T>` is created from a `SharedTrc<T>` or `Weak<T>`.
+
+use std::{cell::RefCell, ptr::NonNull};
+
+/// Maximum number of free local thread reference counts kept by each thread.
+const MAX_POOLED: usize = 1024;
+
+struct Pool {
+    free: Vec<NonNull<usize>>,
+}
+
+impl Drop for Pool {
+    fn drop(&mut self) {
+        for threadref in self.free.drain(..) {
+            drop(unsafe { Box::from_raw(threadref.as_ptr()) });
+        }
+    }
+}
+
+thread_local! {
+    static POOL: RefCell<Pool> = const { RefCell::new(Pool { free: Vec::new() }) };
+}
+
+/// Take a local thread reference count from this thread's pool, or allocate one if the pool is empty.
+/// The returned count is initialized to 1.
+#[inline]
+pub(crate) fn alloc() -> NonNull<usize> {
+    match POOL.try_with(|pool| pool.borrow_mut().free.pop()) {
+        Ok(Some(threadref)) => {
+            unsafe { threadref.as_ptr().write(1) };
+            threadref
+        }
+        _ => NonNull::from(Box::leak(Box::new(1))),
+    }
+}
+
+/// Return a local thread reference count to this thread's pool. If the pool is full or has already been destroyed
+/// because the thread is exiting, the count is freed.
+///
+/// # Safety
+/// The pointer must have come from `alloc` on this thread and must not be used afterwards.
+#[inline]
+pub(crate) unsafe fn free(threadref: NonNull<usize>) {
+    let pooled = POOL
+        .try_with(|pool| {
+            let free = &mut pool.borrow_mut().free;
+            if free.len() < MAX_POOLED {
+                free.push(threadref);
+                true
+            } else {
+                false
+            }
+        })
+        .unwrap_or(false);
+
+    if !pooled {
+        drop(Box::from_raw(threadref.as_ptr()));
+    }
+}
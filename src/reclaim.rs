@@ -0,0 +1,326 @@
+//! Background reclamation of large values, enabled by the `reclaim` feature.
+//!
+//! Dropping a large value, such as a `Vec` with many elements, and freeing its allocation can take long enough to cause
+//! latency spikes on the thread which releases the last reference to it. An allocation created with
+//! [`Trc::new_deferred`] or [`Trc::new_deferred_slice`] records how to release it in its header. When the last strong
+//! reference to it is released, from a `Trc<T>`, `SharedTrc<T>` or an upgraded `Weak<T>`, its value is dropped on a
+//! background thread, which also frees the allocation if no `Weak<T>`s remain. If the last `Weak<T>` is released
+//! later, the allocation is freed there as well.
+//!
+//! Values whose size is below the [threshold](set_threshold) are released on the releasing thread, because sending
+//! them would cost more than dropping them. The size of a slice is the size of its elements, and the size of other
+//! values is provided to [`Trc::new_deferred`], since it usually includes memory the value owns through pointers.
+//!
+//! ## Examples
+//! ```
+//! use trc::{reclaim, Trc};
+//!
+//! let trc = Trc::<[u64]>::new_deferred_slice(vec![0; 1 << 20]);
+//! drop(trc);
+//!
+//! //Wait for the background thread to drop the slice
+//! reclaim::flush();
+//! ```
+
+use std::{
+    alloc::Layout,
+    mem,
+    ptr::{self, addr_of_mut, slice_from_raw_parts_mut, NonNull},
+    sync::{
+        atomic::{fence, AtomicUsize, Ordering},
+        mpsc::{self, Sender},
+        Arc, Condvar, Mutex, OnceLock,
+    },
+    thread,
+};
+
+use allocator_api2::alloc::{Allocator, Global};
+
+use crate::{sub_value, SharedTrcInternal, Trc};
+
+static THRESHOLD: AtomicUsize = AtomicUsize::new(0);
+static RECLAIMER: OnceLock<Option<Reclaimer>> = OnceLock::new();
+
+/// Number of values which have been sent to the background thread and not dropped yet.
+type Pending = Arc<(Mutex<usize>, Condvar)>;
+
+/// Work which is done on the background thread.
+type Job = Box<dyn FnOnce() + Send>;
+
+struct Reclaimer {
+    //`Sender` is only `Sync` on newer versions of Rust
+    sender: Mutex<Sender<Job>>,
+    pending: Pending,
+}
+
+/// Get the reclaimer, starting its background thread if needed. Returns [`None`] if the thread cannot be started.
+fn reclaimer() -> Option<&'static Reclaimer> {
+    RECLAIMER
+        .get_or_init(|| {
+            let (sender, receiver) = mpsc::channel::<Job>();
+            let pending: Pending = Arc::default();
+
+            let counter = pending.clone();
+            thread::Builder::new()
+                .name(String::from("trc-reclaim"))
+                .spawn(move || {
+                    for job in receiver {
+                        job();
+
+                        let (count, drained) = &*counter;
+                        let mut count = count.lock().unwrap_or_else(|e| e.into_inner());
+                        *count -= 1;
+                        if *count == 0 {
+                            drained.notify_all();
+                        }
+                    }
+                })
+                .ok()?;
+
+            Some(Reclaimer {
+                sender: Mutex::new(sender),
+                pending,
+            })
+        })
+        .as_ref()
+}
+
+/// Run a job on the background thread, or on this thread if it cannot be sent.
+fn defer(job: Job) {
+    let Some(reclaimer) = reclaimer() else {
+        return job();
+    };
+
+    let (count, _) = &*reclaimer.pending;
+    *count.lock().unwrap_or_else(|e| e.into_inner()) += 1;
+
+    let sender = reclaimer.sender.lock().unwrap_or_else(|e| e.into_inner());
+    if let Err(mpsc::SendError(job)) = sender.send(job) {
+        drop(sender);
+        *count.lock().unwrap_or_else(|e| e.into_inner()) -= 1;
+        job();
+    }
+}
+
+/// Set the size in bytes below which values are released on the releasing thread instead of the background thread.
+///
+/// # Examples
+/// ```
+/// use trc::{reclaim, Trc};
+///
+/// reclaim::set_threshold(1024);
+/// assert_eq!(reclaim::threshold(), 1024);
+/// reclaim::set_threshold(0);
+/// ```
+#[inline]
+pub fn set_threshold(bytes: usize) {
+    THRESHOLD.store(bytes, Ordering::Relaxed);
+}
+
+/// Get the size in bytes below which values are released on the releasing thread, see [`set_threshold`].
+///
+/// # Examples
+/// ```
+/// use trc::reclaim;
+///
+/// println!("Values smaller than {} bytes are released inline", reclaim::threshold());
+/// ```
+#[inline]
+pub fn threshold() -> usize {
+    THRESHOLD.load(Ordering::Relaxed)
+}
+
+/// Block until all values and allocations which have been handed to the background thread are released.
+///
+/// # Examples
+/// ```
+/// use trc::{reclaim, Trc};
+///
+/// drop(Trc::<[String]>::new_deferred_slice(vec![String::from("deferred"); 1000]));
+/// reclaim::flush();
+/// ```
+pub fn flush() {
+    let Some(reclaimer) = RECLAIMER.get().and_then(Option::as_ref) else {
+        return;
+    };
+
+    let (count, drained) = &*reclaimer.pending;
+    let mut count = count.lock().unwrap_or_else(|e| e.into_inner());
+    while *count > 0 {
+        count = drained.wait(count).unwrap_or_else(|e| e.into_inner());
+    }
+}
+
+/// Part of an allocation which is released.
+#[derive(Clone, Copy)]
+pub(crate) enum Part {
+    /// The value, after the last strong reference is released. The implicit weak reference held by the strong
+    /// references is released with it.
+    Value,
+    /// The allocation, after the last weak reference is released.
+    Allocation,
+}
+
+/// How an allocation created by `Trc::new_deferred` or `Trc::new_deferred_slice` is released. It is stored in the
+/// header of the allocation, which is `None` for other allocations.
+#[derive(Clone, Copy)]
+pub(crate) struct Deferral {
+    /// Release a part of the allocation on the background thread from its thin pointer and `meta`. Returns `false`
+    /// if it is below the threshold, and must be released by the caller instead.
+    release: unsafe fn(NonNull<u8>, usize, Part) -> bool,
+    /// Size of the value in bytes, or the length of a slice.
+    meta: usize,
+}
+
+/// Release a part of an allocation on the background thread, if it was created by `Trc::new_deferred` or
+/// `Trc::new_deferred_slice` and is not below the threshold. Returns `false` if the caller must release it.
+///
+/// # Safety
+/// `shared` must be the last strong reference to the allocation for `Part::Value`, or the last weak reference for
+/// `Part::Allocation`, and must not be used afterwards if `true` is returned.
+#[inline]
+pub(crate) unsafe fn release<T: ?Sized>(shared: NonNull<SharedTrcInternal<T>>, part: Part) -> bool {
+    match *addr_of_mut!((*shared.as_ptr()).reclaim) {
+        Some(deferral) => (deferral.release)(shared.cast(), deferral.meta, part),
+        None => false,
+    }
+}
+
+/// Data of an allocation whose release can be deferred: `T` where `T: Send` and `[T]` where `T: Send`.
+trait DeferredData: Send + 'static {
+    /// Rebuild the pointer to the allocation from its thin pointer and the `meta` of its `Deferral`.
+    fn from_parts(ptr: NonNull<u8>, meta: usize) -> *mut SharedTrcInternal<Self>;
+
+    /// Size in bytes of the value described by the `meta` of its `Deferral`.
+    fn size(meta: usize) -> usize;
+}
+
+impl<T: Send + 'static> DeferredData for T {
+    #[inline]
+    fn from_parts(ptr: NonNull<u8>, _: usize) -> *mut SharedTrcInternal<T> {
+        ptr.as_ptr() as *mut SharedTrcInternal<T>
+    }
+
+    #[inline]
+    fn size(meta: usize) -> usize {
+        meta
+    }
+}
+
+impl<T: Send + 'static> DeferredData for [T] {
+    #[inline]
+    fn from_parts(ptr: NonNull<u8>, meta: usize) -> *mut SharedTrcInternal<[T]> {
+        slice_from_raw_parts_mut(ptr.as_ptr() as *mut T, meta) as *mut SharedTrcInternal<[T]>
+    }
+
+    #[inline]
+    fn size(meta: usize) -> usize {
+        meta.saturating_mul(mem::size_of::<T>())
+    }
+}
+
+/// Pointer to an allocation which is released on the background thread.
+struct SendPtr(NonNull<u8>);
+
+//The data is `Send`, and no other reference uses the released part of the allocation
+unsafe impl Send for SendPtr {}
+
+impl SendPtr {
+    /// Get the pointer. Closures capture the whole `SendPtr` through this, instead of the pointer which is not `Send`.
+    #[inline]
+    fn get(self) -> NonNull<u8> {
+        self.0
+    }
+}
+
+/// `Deferral::release` for a `SharedTrcInternal<D>` allocated with `Global`.
+unsafe fn release_deferred<D: ?Sized + DeferredData>(
+    ptr: NonNull<u8>,
+    meta: usize,
+    part: Part,
+) -> bool {
+    if D::size(meta) < threshold() {
+        return false;
+    }
+
+    let ptr = SendPtr(ptr);
+    defer(Box::new(move || unsafe {
+        let ptr = ptr.get();
+        let shared = D::from_parts(ptr, meta);
+        if let Part::Value = part {
+            ptr::drop_in_place(addr_of_mut!((*shared).data));
+
+            //Release the implicit weak reference
+            if sub_value(&(*shared).weakcount, 1, Ordering::Release) != 1 {
+                return;
+            }
+            fence(Ordering::Acquire);
+        }
+
+        let layout = Layout::for_value(&*shared);
+        Global.deallocate(ptr, layout);
+    }));
+    true
+}
+
+/// Record in the header of a new allocation that it is released on the background thread.
+#[inline]
+fn defer_release<T: ?Sized + DeferredData>(trc: &Trc<T>, meta: usize) {
+    unsafe {
+        *addr_of_mut!((*trc.shared.as_ptr()).reclaim) = Some(Deferral {
+            release: release_deferred::<T>,
+            meta,
+        })
+    };
+}
+
+impl<T: Send + 'static> Trc<T> {
+    /// Creates a new `Trc<T>` from the provided data. When the last strong reference is released, from a `Trc<T>`,
+    /// `SharedTrc<T>` or an upgraded `Weak<T>`, the data is dropped and the allocation is freed on a background
+    /// thread instead of the releasing thread, unless `size` is smaller than the [threshold](set_threshold). `size` is
+    /// the number of bytes which dropping the value frees, such as the capacity of a `Vec<T>` times the size of its
+    /// elements.
+    ///
+    /// # Examples
+    /// ```
+    /// use trc::{reclaim, Trc};
+    ///
+    /// let vec = vec![1u64, 2, 3];
+    /// let size = vec.capacity() * std::mem::size_of::<u64>();
+    /// let trc = Trc::new_deferred(vec, size);
+    /// assert_eq!(trc.len(), 3);
+    ///
+    /// drop(trc);
+    /// reclaim::flush();
+    /// ```
+    #[inline]
+    pub fn new_deferred(value: T, size: usize) -> Self {
+        let trc = Trc::new(value);
+        defer_release(&trc, size);
+        trc
+    }
+}
+
+impl<T: Send + 'static> Trc<[T]> {
+    /// Creates a new `Trc<[T]>` by moving the elements of a `Vec<T>`. When the last strong reference is released, the
+    /// elements are dropped and the allocation is freed on a background thread instead of the releasing thread,
+    /// unless the size of the elements is smaller than the [threshold](set_threshold).
+    ///
+    /// # Examples
+    /// ```
+    /// use trc::{reclaim, Trc};
+    ///
+    /// let trc = Trc::<[String]>::new_deferred_slice(vec![String::from("a"), String::from("b")]);
+    /// assert_eq!(*trc, ["a", "b"]);
+    ///
+    /// drop(trc);
+    /// reclaim::flush();
+    /// ```
+    #[inline]
+    pub fn new_deferred_slice(value: Vec<T>) -> Self {
+        let len = value.len();
+        let trc = Trc::from(value);
+        defer_release(&trc, len);
+        trc
+    }
+}
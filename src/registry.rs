@@ -0,0 +1,41 @@
+//! Per-thread registry of local thread reference counts, enabled by the `registry` feature.
+//!
+//! Each thread maps the address of a `SharedTrcInternal<T>` to the local thread reference count which that thread
+//! uses for it. A `Trc<T>` created with `Trc::new` is only registered once a `SharedTrc<T>` or `Weak<T>` is created
+//! from it. When a `SharedTrc<T>` or `Weak<T>` is converted into a `Trc<T>` on a thread that already has one for
+//! the same allocation, the existing local thread reference count is reused. This keeps the atomic reference count
+//! equal to the number of threads using the data.
+
+use std::{cell::RefCell, collections::HashMap, ptr::NonNull};
+
+thread_local! {
+    static REGISTRY: RefCell<HashMap<usize, NonNull<usize>>> = RefCell::new(HashMap::new());
+}
+
+/// Get the local thread reference count registered for the allocation at `key` on this thread.
+#[inline]
+pub(crate) fn get(key: usize) -> Option<NonNull<usize>> {
+    REGISTRY
+        .try_with(|registry| registry.borrow().get(&key).copied())
+        .ok()
+        .flatten()
+}
+
+/// Register the local thread reference count for the allocation at `key` on this thread.
+/// If the registry has already been destroyed because the thread is exiting, nothing is registered.
+#[inline]
+pub(crate) fn insert(key: usize, threadref: NonNull<usize>) {
+    let _ = REGISTRY.try_with(|registry| registry.borrow_mut().insert(key, threadref));
+}
+
+/// Remove the local thread reference count for the allocation at `key` from this thread's registry.
+/// Most local thread reference counts are never registered, so an empty registry is not searched.
+#[inline]
+pub(crate) fn remove(key: usize) {
+    let _ = REGISTRY.try_with(|registry| {
+        let mut registry = registry.borrow_mut();
+        if !registry.is_empty() {
+            registry.remove(&key);
+        }
+    });
+}
@@ -0,0 +1,131 @@
+//! `Serialize` and `Deserialize` implementations, enabled by the `serde` feature.
+//!
+//! `Trc<T>` and `SharedTrc<T>` are transparent: they serialize as the value they point to. `Trc<[T]>` and `Trc<str>`
+//! deserialize into a single allocation. The elements of a sequence are written directly into it, which grows if the
+//! format does not report the length of the sequence, or reports a wrong one. `Weak<T>` serializes as an `Option<T>`
+//! of the upgraded value.
+
+use std::{fmt, marker::PhantomData, mem};
+
+use allocator_api2::alloc::{Allocator, Global};
+use serde::{
+    de::{self, SeqAccess, Visitor},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+
+use crate::{SharedTrc, SliceBuilder, Trc, Weak};
+
+/// Largest number of bytes which is allocated up front for a sequence of a length reported by the format, so that a
+/// malicious length cannot exhaust memory before any element is read. Longer sequences grow the allocation.
+const MAX_PREALLOC_BYTES: usize = 1024 * 1024;
+
+impl<T: ?Sized + Serialize, A: Allocator> Serialize for Trc<T, A> {
+    #[inline]
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        (**self).serialize(serializer)
+    }
+}
+
+impl<T: ?Sized + Serialize, A: Allocator> Serialize for SharedTrc<T, A> {
+    #[inline]
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        (**self).serialize(serializer)
+    }
+}
+
+impl<T: ?Sized + Serialize, A: Allocator + Clone> Serialize for Weak<T, A> {
+    /// Serialize the value as `Some` if it can be upgraded, and as `None` if it has been dropped.
+    #[inline]
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        Weak::upgrade(self).as_deref().serialize(serializer)
+    }
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for Trc<T> {
+    #[inline]
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        T::deserialize(deserializer).map(Trc::new)
+    }
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for SharedTrc<T> {
+    #[inline]
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Trc::deserialize(deserializer).map(SharedTrc::from)
+    }
+}
+
+impl<'de> Deserialize<'de> for Trc<str> {
+    #[inline]
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_str(StrVisitor)
+    }
+}
+
+impl<'de> Deserialize<'de> for SharedTrc<str> {
+    #[inline]
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Trc::<str>::deserialize(deserializer).map(SharedTrc::from)
+    }
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for Trc<[T]> {
+    #[inline]
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_seq(SliceVisitor(PhantomData))
+    }
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for SharedTrc<[T]> {
+    #[inline]
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Trc::<[T]>::deserialize(deserializer).map(SharedTrc::from)
+    }
+}
+
+/// Copies a borrowed or transient string into a new `Trc<str>`.
+struct StrVisitor;
+
+impl<'de> Visitor<'de> for StrVisitor {
+    type Value = Trc<str>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a string")
+    }
+
+    #[inline]
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<Self::Value, E> {
+        Ok(Trc::from(value))
+    }
+
+    #[inline]
+    fn visit_bytes<E: de::Error>(self, value: &[u8]) -> Result<Self::Value, E> {
+        match std::str::from_utf8(value) {
+            Ok(value) => Ok(Trc::from(value)),
+            Err(_) => Err(E::invalid_value(de::Unexpected::Bytes(value), &self)),
+        }
+    }
+}
+
+/// Deserializes the elements of a sequence into a new `Trc<[T]>`.
+struct SliceVisitor<T>(PhantomData<T>);
+
+impl<'de, T: Deserialize<'de>> Visitor<'de> for SliceVisitor<T> {
+    type Value = Trc<[T]>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a sequence")
+    }
+
+    fn visit_seq<S: SeqAccess<'de>>(self, mut seq: S) -> Result<Self::Value, S::Error> {
+        //The reported length is only trusted up to a limit, and the allocation grows if it is too small
+        let max = MAX_PREALLOC_BYTES / mem::size_of::<T>().max(1);
+        let mut builder = SliceBuilder::new(seq.size_hint().unwrap_or(0).min(max), &Global);
+        while let Some(elem) = seq.next_element()? {
+            builder.push_grow(elem);
+        }
+
+        let shared = builder.finish_shrink();
+        Ok(unsafe { Trc::from_inner_inline(shared, Global) })
+    }
+}
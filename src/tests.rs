@@ -1,6 +1,8 @@
 use std::thread;
 
-use crate::{SharedTrc, Trc, Weak};
+use crate::{
+    coerce_trc, AtomicOptionTrc, AtomicTrc, HeaderSlice, SharedTrc, ThinTrc, Trc, UniqueTrc, Weak,
+};
 
 struct Data {
     string: String,
@@ -130,6 +132,33 @@ fn test_dyn() {
     vehicle.drive();
 }
 
+#[test]
+fn test_coerce_trc() {
+    trait Vehicle {
+        fn drive(&self) -> &'static str;
+    }
+
+    struct Truck;
+
+    impl Vehicle for Truck {
+        fn drive(&self) -> &'static str {
+            "Truck is driving"
+        }
+    }
+
+    let vehicle = coerce_trc!(Trc::new(Truck) => dyn Vehicle);
+    assert_eq!(vehicle.drive(), "Truck is driving");
+
+    let shared = SharedTrc::from_trc(&vehicle);
+    let weak = Trc::downgrade(&vehicle);
+    drop(vehicle);
+    assert_eq!(SharedTrc::to_trc(shared).drive(), "Truck is driving");
+    assert!(Weak::upgrade(&weak).is_none());
+
+    let slice = coerce_trc!(Trc::new([1, 2, 3]) => [i32]);
+    assert_eq!(*slice, [1, 2, 3]);
+}
+
 #[test]
 fn test_weak_drop() {
     let trc = Trc::new(100);
@@ -217,7 +246,1490 @@ fn test_dyn2() {
         }
     }
 
-
     let vehicle = Trc::new(Truck);
     <Truck as Vehicle>::drive(&*vehicle);
-}
\ No newline at end of file
+}
+#[test]
+fn test_inline_threadref() {
+    let trc = Trc::new(100);
+    let trc2 = trc.clone();
+    let shared = SharedTrc::from_trc(&trc);
+    drop(trc);
+    let handle = thread::spawn(move || {
+        let trc = SharedTrc::to_trc(shared);
+        assert_eq!(Trc::local_count(&trc), 1);
+        assert_eq!(Trc::atomic_count(&trc), 2);
+        SharedTrc::from_trc(&trc)
+    });
+    let shared = handle.join().unwrap();
+    assert_eq!(Trc::local_count(&trc2), 1);
+    drop(trc2);
+    assert_eq!(*shared, 100);
+    assert_eq!(SharedTrc::atomic_count(&shared), 1);
+}
+
+#[test]
+fn test_into_inner_clones() {
+    let trc = Trc::new(String::from("Trc"));
+    let trc2 = trc.clone();
+    assert!(Trc::into_inner(trc).is_none());
+    assert_eq!(Trc::local_count(&trc2), 1);
+    assert_eq!(Trc::into_inner(trc2).as_deref(), Some("Trc"));
+
+    let trc = Trc::new(100);
+    let trc2 = trc.clone();
+    let ptr = Trc::into_raw(trc);
+    assert_eq!(Trc::local_count(&trc2), 1);
+    assert_eq!(Trc::atomic_count(&trc2), 2);
+    drop(unsafe { Trc::from_raw(ptr) });
+    assert_eq!(*trc2, 100);
+}
+
+#[cfg(feature = "registry")]
+#[test]
+fn test_registry() {
+    let trc = Trc::new(100);
+    let shared = SharedTrc::from_trc(&trc);
+    assert_eq!(Trc::atomic_count(&trc), 2);
+    let trc2 = SharedTrc::to_trc(shared);
+    assert_eq!(Trc::local_count(&trc), 2);
+    assert_eq!(Trc::atomic_count(&trc), 1);
+
+    let weak = Trc::downgrade(&trc);
+    let trc3 = Weak::upgrade(&weak).unwrap();
+    assert_eq!(Trc::local_count(&trc), 3);
+    assert_eq!(Trc::atomic_count(&trc), 1);
+    drop((trc, trc2, trc3));
+    assert!(Weak::upgrade(&weak).is_none());
+
+    //`Trc::new` registers lazily, once a handle which can come back to this thread is created
+    let trc = Trc::new(100);
+    let key =
+        Trc::as_ptr(&trc) as *const u8 as usize - crate::data_offset(std::mem::align_of::<i32>());
+    assert!(crate::registry::get(key).is_none());
+    let shared = SharedTrc::from(trc.clone());
+    assert!(crate::registry::get(key).is_some());
+    let trc2 = SharedTrc::to_trc(shared);
+    assert_eq!(Trc::local_count(&trc), 2);
+    assert_eq!(Trc::atomic_count(&trc), 1);
+    drop((trc, trc2));
+    assert!(crate::registry::get(key).is_none());
+}
+
+#[cfg(feature = "registry")]
+#[test]
+fn test_registry_multithread() {
+    let trc = Trc::new(100);
+    let shared1 = SharedTrc::from_trc(&trc);
+    let shared2 = SharedTrc::from_trc(&trc);
+    let handle = thread::spawn(move || {
+        let trc1 = SharedTrc::to_trc(shared1);
+        let trc2 = SharedTrc::to_trc(shared2);
+        assert_eq!(Trc::local_count(&trc1), 2);
+        assert_eq!(Trc::atomic_count(&trc2), 2);
+    });
+    handle.join().unwrap();
+    assert_eq!(Trc::atomic_count(&trc), 1);
+}
+
+#[cfg(feature = "pool")]
+#[test]
+fn test_pool() {
+    let threadref = crate::alloc_threadref();
+    unsafe { crate::free_threadref(threadref) };
+    let threadref2 = crate::alloc_threadref();
+    assert_eq!(threadref, threadref2);
+    assert_eq!(unsafe { *threadref2.as_ptr() }, 1);
+    unsafe { crate::free_threadref(threadref2) };
+
+    let trc = Trc::new(100);
+    let handle = thread::spawn({
+        let shared = SharedTrc::from_trc(&trc);
+        move || {
+            for _ in 0..100 {
+                let trc = SharedTrc::to_trc(shared.clone());
+                assert_eq!(*trc, 100);
+            }
+        }
+    });
+    handle.join().unwrap();
+    assert_eq!(Trc::atomic_count(&trc), 1);
+}
+
+#[derive(Clone, Default)]
+struct CountingAlloc {
+    allocated: std::sync::Arc<std::sync::atomic::AtomicIsize>,
+}
+
+unsafe impl allocator_api2::alloc::Allocator for CountingAlloc {
+    fn allocate(
+        &self,
+        layout: std::alloc::Layout,
+    ) -> Result<std::ptr::NonNull<[u8]>, allocator_api2::alloc::AllocError> {
+        self.allocated
+            .fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        allocator_api2::alloc::Global.allocate(layout)
+    }
+
+    unsafe fn deallocate(&self, ptr: std::ptr::NonNull<u8>, layout: std::alloc::Layout) {
+        self.allocated
+            .fetch_sub(1, std::sync::atomic::Ordering::SeqCst);
+        allocator_api2::alloc::Global.deallocate(ptr, layout)
+    }
+}
+
+impl CountingAlloc {
+    fn live(&self) -> isize {
+        self.allocated.load(std::sync::atomic::Ordering::SeqCst)
+    }
+}
+
+#[test]
+fn test_allocator() {
+    let alloc = CountingAlloc::default();
+
+    let trc = Trc::new_in(String::from("Trc"), alloc.clone());
+    assert_eq!(alloc.live(), 1);
+    let weak = Trc::downgrade(&trc);
+    let shared = SharedTrc::from_trc(&trc);
+    drop(trc);
+    let handle = thread::spawn(move || {
+        let trc = SharedTrc::to_trc(shared);
+        assert_eq!(*trc, "Trc");
+    });
+    handle.join().unwrap();
+    assert!(Weak::upgrade(&weak).is_none());
+    assert_eq!(alloc.live(), 1);
+    drop(weak);
+    assert_eq!(alloc.live(), 0);
+
+    let mut slice = Trc::<[i32], _>::new_uninit_slice_in(3, alloc.clone());
+    for (i, elem) in Trc::get_mut(&mut slice).unwrap().iter_mut().enumerate() {
+        elem.write(i as i32);
+    }
+    let slice = unsafe { slice.assume_init() };
+    assert_eq!(*slice, [0, 1, 2]);
+    drop(slice);
+    assert_eq!(alloc.live(), 0);
+
+    let trc = Trc::new_in(100, alloc.clone());
+    let (ptr, a) = Trc::into_raw_with_allocator(trc);
+    let shared = unsafe { SharedTrc::from_raw_in(ptr, a) };
+    assert_eq!(alloc.live(), 1);
+    drop(shared);
+    assert_eq!(alloc.live(), 0);
+
+    struct Node(Weak<Node, CountingAlloc>);
+    let node = Trc::new_cyclic_in(|weak| Node(weak.clone()), alloc.clone());
+    assert!(Trc::ptr_eq(&Weak::upgrade(&node.0).unwrap(), &node));
+    assert_eq!(Trc::try_unwrap(node).ok().map(|_| ()), Some(()));
+    assert_eq!(alloc.live(), 0);
+}
+
+struct FailingAlloc;
+
+unsafe impl allocator_api2::alloc::Allocator for FailingAlloc {
+    fn allocate(
+        &self,
+        _layout: std::alloc::Layout,
+    ) -> Result<std::ptr::NonNull<[u8]>, allocator_api2::alloc::AllocError> {
+        Err(allocator_api2::alloc::AllocError)
+    }
+
+    unsafe fn deallocate(&self, _ptr: std::ptr::NonNull<u8>, _layout: std::alloc::Layout) {
+        unreachable!()
+    }
+}
+
+#[test]
+fn test_try_new() {
+    assert_eq!(*Trc::try_new(100).unwrap(), 100);
+    assert!(Trc::try_new_in(100, FailingAlloc).is_err());
+    assert!(Trc::<i32, _>::try_new_uninit_in(FailingAlloc).is_err());
+
+    assert_eq!(Trc::<[i32]>::try_new_uninit_slice(3).unwrap().len(), 3);
+    assert!(Trc::<[i32], _>::try_new_uninit_slice_in(3, FailingAlloc).is_err());
+    assert!(Trc::<[u64]>::try_new_uninit_slice(usize::MAX).is_err());
+
+    assert_eq!(
+        *Trc::<[i32]>::try_from_iter(vec![1, 2, 3]).unwrap(),
+        [1, 2, 3]
+    );
+    assert!(Trc::<[i32], _>::try_from_iter_in(vec![1, 2, 3], FailingAlloc).is_err());
+
+    let alloc = CountingAlloc::default();
+    let trc = Trc::<[String], _>::try_from_iter_in(
+        vec![String::from("a"), String::from("b")],
+        alloc.clone(),
+    )
+    .unwrap();
+    assert_eq!(alloc.live(), 1);
+    drop(trc);
+    assert_eq!(alloc.live(), 0);
+}
+
+#[test]
+fn test_make_mut() {
+    let mut trc = Trc::new(String::from("a"));
+    let ptr = Trc::as_ptr(&trc);
+    Trc::make_mut(&mut trc).push('b');
+    assert_eq!(Trc::as_ptr(&trc), ptr);
+
+    let other = trc.clone();
+    Trc::make_mut(&mut trc).push('c');
+    assert_eq!(*trc, "abc");
+    assert_eq!(*other, "ab");
+    assert_eq!(Trc::local_count(&trc), 1);
+    assert_eq!(Trc::local_count(&other), 1);
+
+    let shared = SharedTrc::from_trc(&trc);
+    Trc::make_mut(&mut trc).push('d');
+    assert_eq!(*trc, "abcd");
+    assert_eq!(*shared, "abc");
+    drop(shared);
+
+    let weak = Trc::downgrade(&trc);
+    Trc::make_mut(&mut trc).push('e');
+    assert_eq!(*trc, "abcde");
+    assert!(Weak::upgrade(&weak).is_none());
+    assert_eq!(Trc::weak_count(&trc), 1);
+    drop(weak);
+
+    let alloc = CountingAlloc::default();
+    let mut slice = Trc::<[String], _>::try_from_iter_in(
+        vec![String::from("a"), String::from("b")],
+        alloc.clone(),
+    )
+    .unwrap();
+    let weak = Trc::downgrade(&slice);
+    Trc::make_mut(&mut slice)[0].push('a');
+    assert!(Weak::upgrade(&weak).is_none());
+    assert_eq!(alloc.live(), 2);
+    drop(weak);
+    assert_eq!(alloc.live(), 1);
+
+    let other = slice.clone();
+    Trc::make_mut(&mut slice)[1].push('b');
+    assert_eq!(*slice, ["aa", "bb"]);
+    assert_eq!(*other, ["aa", "b"]);
+    drop((slice, other));
+    assert_eq!(alloc.live(), 0);
+}
+
+#[test]
+fn test_shared_ownership() {
+    let alloc = CountingAlloc::default();
+
+    let mut shared = SharedTrc::from(Trc::new_in(String::from("a"), alloc.clone()));
+    SharedTrc::get_mut(&mut shared).unwrap().push('b');
+    let shared2 = shared.clone();
+    assert!(SharedTrc::get_mut(&mut shared).is_none());
+    SharedTrc::make_mut(&mut shared).push('c');
+    assert_eq!(alloc.live(), 2);
+    assert_eq!(*shared, "abc");
+    assert_eq!(*shared2, "ab");
+
+    let trc = SharedTrc::to_trc(shared2.clone());
+    let weak = Trc::downgrade(&trc);
+    let shared2 = SharedTrc::try_unwrap(shared2).unwrap_err();
+    drop(trc);
+    assert_eq!(SharedTrc::into_inner(shared2).as_deref(), Some("ab"));
+    assert!(Weak::upgrade(&weak).is_none());
+    drop(weak);
+    assert_eq!(alloc.live(), 1);
+
+    let weak = Trc::downgrade(&SharedTrc::to_trc(shared.clone()));
+    SharedTrc::make_mut(&mut shared).push('d');
+    assert!(Weak::upgrade(&weak).is_none());
+    drop(weak);
+    assert_eq!(alloc.live(), 1);
+    assert_eq!(SharedTrc::unwrap_or_clone(shared), "abcd");
+    assert_eq!(alloc.live(), 0);
+
+    let dropped = std::sync::Arc::new(());
+    let trc = Trc::new(dropped.clone());
+    let weak = Trc::downgrade(&trc);
+    drop(SharedTrc::from(trc));
+    assert_eq!(std::sync::Arc::strong_count(&dropped), 1);
+    drop(weak);
+}
+
+#[test]
+fn test_upgrade_shared() {
+    let shared: SharedTrc<_> = Trc::new(100).into();
+    let weak = SharedTrc::downgrade(&shared);
+    assert_eq!(SharedTrc::weak_count(&shared), 2);
+
+    let handle = thread::spawn({
+        let weak = weak.clone();
+        move || {
+            let shared = Weak::upgrade_shared(&weak).unwrap();
+            assert_eq!(*shared, 100);
+            assert_eq!(SharedTrc::atomic_count(&shared), 2);
+        }
+    });
+    handle.join().unwrap();
+
+    assert_eq!(SharedTrc::atomic_count(&shared), 1);
+    drop(shared);
+    assert!(Weak::upgrade_shared(&weak).is_none());
+    assert!(Weak::upgrade(&weak).is_none());
+}
+
+#[test]
+fn test_weak_api() {
+    let root: Weak<i32> = Weak::new();
+    assert!(Weak::upgrade(&root).is_none());
+    assert!(Weak::upgrade_shared(&root).is_none());
+    assert_eq!(Weak::strong_count(&root), 0);
+    assert_eq!(Weak::weak_count(&root), 0);
+    assert!(Weak::ptr_eq(&root, &root.clone()));
+    let ptr = Weak::into_raw(root);
+    let root = unsafe { Weak::from_raw(ptr) };
+    assert!(Weak::ptr_eq(&root, &Weak::default()));
+
+    let trc = Trc::new(100);
+    let weak = Trc::downgrade(&trc);
+    assert!(!Weak::ptr_eq(&weak, &root));
+    assert_eq!(Weak::as_ptr(&weak), Trc::as_ptr(&trc));
+    assert_eq!(Weak::strong_count(&weak), 1);
+    assert_eq!(Weak::weak_count(&weak), 2);
+
+    let ptr = Weak::into_raw(weak);
+    assert_eq!(Trc::weak_count(&trc), 2);
+    let weak = unsafe { Weak::from_raw(ptr) };
+    assert_eq!(*Weak::upgrade(&weak).unwrap(), 100);
+
+    drop(trc);
+    assert_eq!(Weak::strong_count(&weak), 0);
+    assert_eq!(Weak::weak_count(&weak), 1);
+}
+
+#[cfg(feature = "nightly")]
+#[test]
+fn test_coerce_unsized() {
+    let display: Trc<dyn std::fmt::Display> = Trc::new(100);
+    let shared: SharedTrc<dyn std::fmt::Display> = SharedTrc::from_trc(&display);
+    let weak: Weak<dyn std::fmt::Display> = Weak::<i32>::new();
+    assert_eq!(shared.to_string(), "100");
+    assert!(Weak::upgrade(&weak).is_none());
+}
+
+#[test]
+fn test_downcast() {
+    use std::any::Any;
+
+    let trc = Trc::new(String::from("Trc"));
+    let trc2 = trc.clone();
+    let any = coerce_trc!(trc => dyn Any);
+    let Err(any) = any.downcast::<i32>() else {
+        panic!()
+    };
+    let trc = any.downcast::<String>().ok().unwrap();
+    assert!(Trc::ptr_eq(&trc, &trc2));
+    assert_eq!(Trc::local_count(&trc), 2);
+    assert_eq!(Trc::atomic_count(&trc), 1);
+
+    let shared = coerce_trc!(SharedTrc::from_trc(&trc) => dyn Any + Send + Sync);
+    let handle = thread::spawn(move || {
+        let shared = shared.downcast::<String>().ok().unwrap();
+        assert_eq!(*shared, "Trc");
+        assert_eq!(SharedTrc::atomic_count(&shared), 2);
+    });
+    handle.join().unwrap();
+
+    let weak = coerce_trc!(Trc::downgrade(&trc) => dyn Any);
+    let Err(weak) = weak.downcast::<i32>() else {
+        panic!()
+    };
+    let weak = weak.downcast::<String>().ok().unwrap();
+    assert_eq!(Trc::weak_count(&trc), 2);
+    drop((trc, trc2));
+    assert!(Weak::upgrade(&weak).is_none());
+
+    let weak = coerce_trc!(Weak::<i32>::new() => dyn Any);
+    assert!(weak.downcast::<i32>().is_err());
+}
+
+#[test]
+fn test_str() {
+    let trc = Trc::<str>::from("ident");
+    assert_eq!(&*trc, "ident");
+    assert_eq!(trc.to_string(), "ident");
+    assert_eq!(format!("{trc:?}"), "\"ident\"");
+    assert!(trc < Trc::from(String::from("other")));
+    assert_eq!(trc, Trc::from(Box::<str>::from("ident")));
+    assert_eq!(&*Trc::<str>::default(), "");
+
+    let mut set = std::collections::HashSet::new();
+    set.insert(trc.clone());
+    assert!(set.contains("ident"));
+
+    let bytes = Trc::<[u8]>::from(trc.clone());
+    assert_eq!(&*bytes, b"ident");
+    assert_eq!(Trc::local_count(&bytes), 3);
+    let trc2 = Trc::<str>::try_from(bytes).unwrap();
+    assert!(Trc::ptr_eq(&trc, &trc2));
+    assert!(Trc::<str>::try_from(Trc::<[u8]>::from(&[0xc3][..])).is_err());
+
+    let shared = SharedTrc::<str>::from(String::from("shared"));
+    let handle = thread::spawn(move || SharedTrc::to_trc(shared).len());
+    assert_eq!(handle.join().unwrap(), 6);
+}
+
+#[test]
+fn test_from_conversions() {
+    use std::{
+        borrow::Cow,
+        ffi::{CStr, CString, OsStr, OsString},
+        path::{Path, PathBuf},
+        sync::Arc,
+    };
+
+    trait Vehicle {
+        fn wheels(&self) -> usize;
+    }
+
+    #[repr(align(64))]
+    struct Truck(usize);
+
+    impl Vehicle for Truck {
+        fn wheels(&self) -> usize {
+            self.0
+        }
+    }
+
+    let boxed: Box<dyn Vehicle> = Box::new(Truck(18));
+    let trc = Trc::<dyn Vehicle>::from(boxed);
+    assert_eq!(trc.wheels(), 18);
+    assert_eq!(Trc::as_ptr(&trc) as *const u8 as usize % 64, 0);
+    let weak = Trc::downgrade(&trc);
+    drop(trc);
+    assert!(Weak::upgrade(&weak).is_none());
+
+    let trc = Trc::<[String]>::from(vec![String::from("a"), String::from("b")]);
+    assert_eq!(*trc, ["a", "b"]);
+    let trc = Trc::<[String]>::from(Vec::new());
+    assert!(trc.is_empty());
+    let trc = Trc::<str>::from(Box::<str>::from("boxed"));
+    assert_eq!(&*trc, "boxed");
+    let trc = Trc::<()>::from(Box::new(()));
+    assert_eq!(*trc, ());
+
+    let trc = Trc::<str>::from(Cow::Owned(String::from("owned")));
+    assert_eq!(&*trc, "owned");
+    let trc = Trc::<[i32]>::from(Cow::Borrowed(&[1, 2][..]));
+    assert_eq!(*trc, [1, 2]);
+
+    let trc = Trc::<CStr>::from(CString::new("c").unwrap());
+    assert_eq!(trc.to_bytes_with_nul(), b"c\0");
+    assert_eq!(Trc::<CStr>::from(&*trc), trc);
+    let trc = Trc::<Path>::from(PathBuf::from("/a/b"));
+    assert_eq!(trc.parent(), Some(Path::new("/a")));
+    assert_eq!(Trc::<Path>::from(&*trc), trc);
+    let trc = Trc::<OsStr>::from(OsString::from("os"));
+    assert_eq!(Trc::<OsStr>::from(&*trc), trc);
+
+    let arc = Arc::new(String::from("arc"));
+    let arc2 = arc.clone();
+    assert_eq!(*Trc::<String>::from(arc), "arc");
+    let ptr = arc2.as_ptr();
+    let trc: Trc<String> = Trc::from(arc2);
+    assert_eq!(trc.as_ptr(), ptr);
+}
+
+#[test]
+fn test_from_iter() {
+    struct NoClone(i32);
+
+    let trc = Trc::<[NoClone]>::from_iter((0..3).map(NoClone));
+    assert_eq!(trc.iter().map(|x| x.0).collect::<Vec<_>>(), [0, 1, 2]);
+    let trc: Trc<[i32]> = (0..10).filter(|x| x % 2 == 0).collect();
+    assert_eq!(*trc, [0, 2, 4, 6, 8]);
+
+    let dropped = std::sync::Arc::new(());
+    let result = std::panic::catch_unwind(|| {
+        (0..3)
+            .map(|i| {
+                if i == 2 {
+                    panic!("iterator panicked");
+                }
+                dropped.clone()
+            })
+            .collect::<Trc<[_]>>()
+    });
+    assert!(result.is_err());
+    assert_eq!(std::sync::Arc::strong_count(&dropped), 1);
+
+    struct Lying(usize);
+
+    impl Iterator for Lying {
+        type Item = String;
+
+        fn next(&mut self) -> Option<String> {
+            self.0 -= 1;
+            (self.0 > 0).then(String::new)
+        }
+
+        fn size_hint(&self) -> (usize, Option<usize>) {
+            (5, Some(5))
+        }
+    }
+
+    assert!(std::panic::catch_unwind(|| Lying(3).collect::<Trc<[_]>>()).is_err());
+    assert!(std::panic::catch_unwind(|| Lying(10).collect::<Trc<[_]>>()).is_err());
+}
+
+#[test]
+fn test_slice_builder_unwind() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static CLONES: AtomicUsize = AtomicUsize::new(0);
+    static DROPS: AtomicUsize = AtomicUsize::new(0);
+
+    struct PanicOnClone;
+
+    impl Clone for PanicOnClone {
+        fn clone(&self) -> Self {
+            if CLONES.fetch_add(1, Ordering::SeqCst) == 2 {
+                panic!("clone panicked");
+            }
+            PanicOnClone
+        }
+    }
+
+    impl Drop for PanicOnClone {
+        fn drop(&mut self) {
+            DROPS.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    let values = [PanicOnClone, PanicOnClone, PanicOnClone];
+    let result = std::panic::catch_unwind(|| Trc::<[PanicOnClone]>::from(&values[..]));
+    assert!(result.is_err());
+    assert_eq!(DROPS.load(Ordering::SeqCst), 2);
+
+    struct Lying(std::ops::Range<usize>, usize);
+
+    impl Iterator for Lying {
+        type Item = String;
+
+        fn next(&mut self) -> Option<String> {
+            self.0.next().map(|i| i.to_string())
+        }
+    }
+
+    impl ExactSizeIterator for Lying {
+        fn len(&self) -> usize {
+            self.1
+        }
+    }
+
+    let alloc = CountingAlloc::default();
+    for (range, len) in [(0..2, 3), (0..4, 3)] {
+        let result = std::panic::catch_unwind(|| {
+            Trc::<[String], _>::try_from_iter_in(Lying(range, len), alloc.clone())
+        });
+        assert!(result.is_err());
+        assert_eq!(alloc.live(), 0);
+    }
+}
+
+#[test]
+fn test_from_raw_unsized() {
+    trait Vehicle {
+        fn wheels(&self) -> usize;
+    }
+
+    #[repr(align(64))]
+    struct Truck(usize);
+
+    impl Vehicle for Truck {
+        fn wheels(&self) -> usize {
+            self.0
+        }
+    }
+
+    let trc = Trc::<[u8]>::from(&b"buffer"[..]);
+    let ptr = Trc::into_raw(trc);
+    let trc = unsafe { Trc::from_raw(ptr) };
+    assert_eq!(&*trc, b"buffer");
+    assert_eq!(Trc::local_count(&trc), 1);
+
+    let trc = Trc::<str>::from("str");
+    let ptr = SharedTrc::into_raw(SharedTrc::from_trc(&trc));
+    let shared = unsafe { SharedTrc::from_raw(ptr) };
+    assert_eq!(&*shared, "str");
+    assert_eq!(SharedTrc::atomic_count(&shared), 2);
+    drop(shared);
+
+    let trc = coerce_trc!(Trc::new(Truck(18)) => dyn Vehicle);
+    let weak = Trc::downgrade(&trc);
+    let ptr = Trc::into_raw(trc);
+    let trc = unsafe { Trc::from_raw(ptr) };
+    assert_eq!(trc.wheels(), 18);
+    assert!(Trc::ptr_eq(&Weak::upgrade(&weak).unwrap(), &trc));
+}
+
+#[test]
+fn test_alignments() {
+    macro_rules! check_align {
+        ($($align:literal),*) => {$({
+            #[repr(align($align))]
+            #[derive(Clone, Copy, Debug, PartialEq)]
+            struct Aligned(u8);
+
+            let is_aligned = |ptr: *const Aligned| ptr as *const u8 as usize % std::mem::align_of::<Aligned>() == 0;
+
+            let trc = Trc::new(Aligned(1));
+            let weak = Trc::downgrade(&trc);
+            let ptr = Trc::into_raw(trc);
+            assert!(is_aligned(ptr));
+            let trc = unsafe { Trc::from_raw(ptr) };
+            assert_eq!(*trc, Aligned(1));
+
+            let ptr = Weak::into_raw(weak);
+            assert!(is_aligned(ptr));
+            let weak = unsafe { Weak::from_raw(ptr) };
+            assert!(Trc::ptr_eq(&Weak::upgrade(&weak).unwrap(), &trc));
+
+            let ptr = SharedTrc::into_raw(SharedTrc::from_trc(&trc));
+            assert!(is_aligned(ptr));
+            let shared = unsafe { SharedTrc::from_raw(ptr) };
+            assert_eq!(*shared, Aligned(1));
+            drop(shared);
+
+            let slice = Trc::<[Aligned]>::from(&[Aligned(2), Aligned(3), Aligned(4)][..]);
+            let ptr = Trc::into_raw(slice);
+            assert!(is_aligned(ptr as *const Aligned));
+            let slice = unsafe { Trc::from_raw(ptr) };
+            assert_eq!(*slice, [Aligned(2), Aligned(3), Aligned(4)]);
+
+            let ptr = SharedTrc::into_raw(SharedTrc::from_trc(&slice));
+            let shared = unsafe { SharedTrc::from_raw(ptr) };
+            assert_eq!(*shared, [Aligned(2), Aligned(3), Aligned(4)]);
+
+            let slice: Trc<[Aligned]> = (5..8).map(Aligned).collect();
+            assert!(is_aligned(slice.as_ptr()));
+            assert_eq!(*slice, [Aligned(5), Aligned(6), Aligned(7)]);
+        })*};
+    }
+
+    check_align!(1, 2, 4, 8, 16, 32, 64, 128, 256, 512, 1024, 2048, 4096);
+}
+
+#[test]
+fn test_thin() {
+    use std::{mem::size_of, rc::Rc};
+
+    assert_eq!(size_of::<ThinTrc<(), [u8]>>(), size_of::<usize>());
+    assert_eq!(
+        size_of::<Option<ThinTrc<String, [u64]>>>(),
+        size_of::<usize>()
+    );
+    assert_eq!(size_of::<ThinTrc<(), str>>(), size_of::<usize>());
+
+    let header = Rc::new(());
+    let thin =
+        ThinTrc::from_header_and_iter(header.clone(), vec![String::from("a"), String::from("b")]);
+    assert_eq!(*thin, ["a", "b"]);
+    assert!(Rc::ptr_eq(ThinTrc::header(&thin), &header));
+
+    let thin2 = thin.clone();
+    assert!(ThinTrc::ptr_eq(&thin, &thin2));
+    assert_eq!(ThinTrc::local_count(&thin), 2);
+    assert_eq!(ThinTrc::atomic_count(&thin), 1);
+    assert_eq!(thin, thin2);
+    drop(thin);
+    assert_eq!(Rc::strong_count(&header), 2);
+    drop(thin2);
+    assert_eq!(Rc::strong_count(&header), 1);
+
+    let empty = ThinTrc::from(Vec::<String>::new());
+    assert!(empty.is_empty());
+
+    #[repr(align(64))]
+    #[derive(Clone, Debug, PartialEq)]
+    struct Aligned(u8);
+
+    #[repr(align(16))]
+    struct Header(u8);
+
+    let thin = ThinTrc::from_header_and_iter(Header(7), [Aligned(1), Aligned(2)]);
+    assert_eq!(ThinTrc::header(&thin).0, 7);
+    assert_eq!(thin.as_ptr() as usize % 64, 0);
+    assert_eq!(*thin, [Aligned(1), Aligned(2)]);
+
+    let trc: Trc<[Aligned]> = Trc::from(thin);
+    assert_eq!(*trc, [Aligned(1), Aligned(2)]);
+    let thin = ThinTrc::from(trc);
+    assert_eq!(*thin, [Aligned(1), Aligned(2)]);
+
+    struct Lying(usize);
+
+    impl Iterator for Lying {
+        type Item = u8;
+
+        fn next(&mut self) -> Option<u8> {
+            (self.0 < 2).then(|| {
+                self.0 += 1;
+                0
+            })
+        }
+    }
+
+    impl ExactSizeIterator for Lying {
+        fn len(&self) -> usize {
+            3
+        }
+    }
+
+    let result =
+        std::panic::catch_unwind(|| ThinTrc::from_header_and_iter(header.clone(), Lying(0)));
+    assert!(result.is_err());
+    assert_eq!(Rc::strong_count(&header), 1);
+
+    //Unique elements are moved, and shared ones are cloned
+    let thin = ThinTrc::from_header_and_iter(header.clone(), [String::from("a")]);
+    let ptr = thin[0].as_ptr();
+    let trc: Trc<[String]> = Trc::from(thin);
+    assert_eq!(trc[0].as_ptr(), ptr);
+    assert_eq!(Rc::strong_count(&header), 1);
+
+    let trc2 = trc.clone();
+    let thin = ThinTrc::from(trc);
+    assert_ne!(thin[0].as_ptr(), ptr);
+    drop(trc2);
+    let thin2 = thin.clone();
+    let trc: Trc<[String]> = Trc::from(thin);
+    assert_eq!(*trc, *thin2);
+    assert_ne!(trc[0].as_ptr(), thin2[0].as_ptr());
+
+    let name = ThinTrc::from_header_and_str(header.clone(), "name");
+    assert_eq!(&*name, "name");
+    assert_eq!(name.to_string(), "name");
+    assert_eq!(name, name.clone());
+    let trc: Trc<str> = Trc::from(name);
+    assert_eq!(&*trc, "name");
+    assert_eq!(&*ThinTrc::from(trc), "name");
+    assert_eq!(Rc::strong_count(&header), 1);
+}
+
+#[test]
+fn test_header_slice() {
+    use std::rc::Rc;
+
+    let node: Trc<HeaderSlice<String, [u32]>> =
+        Trc::from_header_and_iter(String::from("add"), [1, 2]);
+    assert_eq!(node.header, "add");
+    assert_eq!(node.slice, [1, 2]);
+
+    let shared = SharedTrc::from_trc(&node);
+    let handle = thread::spawn(move || {
+        let node = SharedTrc::to_trc(shared);
+        assert_eq!(node.slice.iter().sum::<u32>(), 3);
+    });
+    handle.join().unwrap();
+    assert_eq!(Trc::atomic_count(&node), 1);
+
+    let alloc = CountingAlloc::default();
+    let header = Rc::new(());
+    let trc = Trc::from_header_and_iter_in(header.clone(), vec![String::from("a")], alloc.clone());
+    assert_eq!(alloc.live(), 1);
+    assert_eq!(Rc::strong_count(&header), 2);
+    drop(trc);
+    assert_eq!(alloc.live(), 0);
+    assert_eq!(Rc::strong_count(&header), 1);
+
+    #[repr(align(64))]
+    #[derive(Debug, PartialEq)]
+    struct Aligned(u8);
+
+    let trc = Trc::from_header_and_iter(1u8, [Aligned(2)]);
+    assert_eq!(trc.slice.as_ptr() as usize % 64, 0);
+    assert_eq!(trc.slice, [Aligned(2)]);
+
+    let result = std::panic::catch_unwind(|| {
+        Trc::from_header_and_iter_in(
+            header.clone(),
+            (0..3).map(|i| if i == 2 { panic!() } else { i }),
+            alloc.clone(),
+        )
+    });
+    assert!(result.is_err());
+    assert_eq!(alloc.live(), 0);
+    assert_eq!(Rc::strong_count(&header), 1);
+}
+
+#[test]
+fn test_unique() {
+    use std::cell::RefCell;
+
+    struct Node {
+        value: i32,
+        neighbors: RefCell<Vec<Weak<Node>>>,
+    }
+
+    fn node(value: i32) -> UniqueTrc<Node> {
+        UniqueTrc::new(Node {
+            value,
+            neighbors: RefCell::new(Vec::new()),
+        })
+    }
+
+    fn link(from: &mut UniqueTrc<Node>, to: &UniqueTrc<Node>) {
+        from.neighbors.get_mut().push(UniqueTrc::downgrade(to));
+    }
+
+    let mut a = node(1);
+    let mut b = node(2);
+    link(&mut a, &b);
+    link(&mut b, &a);
+    a.value += 10;
+
+    let weak_a = UniqueTrc::downgrade(&a);
+    assert!(Weak::upgrade(&weak_a).is_none());
+    assert_eq!(Weak::strong_count(&weak_a), 0);
+    assert_eq!(Weak::weak_count(&weak_a), 3);
+
+    let a = UniqueTrc::into_trc(a);
+    let b: Trc<Node> = b.into();
+    let neighbor = Weak::upgrade(&b.neighbors.borrow()[0]).unwrap();
+    assert!(Trc::ptr_eq(&neighbor, &a));
+    assert_eq!(neighbor.value, 11);
+    assert_eq!(Weak::upgrade(&a.neighbors.borrow()[0]).unwrap().value, 2);
+    drop(neighbor);
+
+    //Break the cycle
+    a.neighbors.borrow_mut().clear();
+    drop(b);
+    drop(a);
+    assert!(Weak::upgrade(&weak_a).is_none());
+
+    let mut unique = UniqueTrc::new(String::from("shared"));
+    unique.push('!');
+    let weak = UniqueTrc::downgrade(&unique);
+    let shared = UniqueTrc::into_shared(unique);
+    let handle = thread::spawn(move || {
+        assert_eq!(*Weak::upgrade(&weak).unwrap(), "shared!");
+    });
+    handle.join().unwrap();
+    assert_eq!(SharedTrc::atomic_count(&shared), 1);
+
+    let alloc = CountingAlloc::default();
+    let unique = UniqueTrc::new_in(vec![1, 2, 3], alloc.clone());
+    let weak = UniqueTrc::downgrade(&unique);
+    drop(unique);
+    assert_eq!(alloc.live(), 1);
+    assert!(Weak::upgrade(&weak).is_none());
+    drop(weak);
+    assert_eq!(alloc.live(), 0);
+}
+
+#[test]
+fn test_atomic() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static DROPS: AtomicUsize = AtomicUsize::new(0);
+
+    struct Config(usize);
+
+    impl Drop for Config {
+        fn drop(&mut self) {
+            DROPS.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    let cell = std::sync::Arc::new(AtomicTrc::new(Trc::new(Config(0)).into()));
+    let readers: Vec<_> = (0..4)
+        .map(|_| {
+            let cell = cell.clone();
+            thread::spawn(move || {
+                let mut last = 0;
+                for _ in 0..1000 {
+                    let config = cell.load();
+                    assert!(config.0 >= last);
+                    last = config.0;
+                    assert_eq!(Trc::local_count(&config), 1);
+                }
+            })
+        })
+        .collect();
+
+    for version in 1..=100 {
+        if version % 2 == 0 {
+            cell.store(Trc::new(Config(version)).into());
+        } else {
+            let current = cell.load();
+            assert!(cell
+                .compare_and_swap(&current, Trc::new(Config(version)).into())
+                .is_ok());
+        }
+    }
+    for reader in readers {
+        reader.join().unwrap();
+    }
+
+    let current = cell.load();
+    assert_eq!(current.0, 100);
+    let stale = Trc::new(Config(0));
+    assert!(cell
+        .compare_and_swap(&stale, Trc::new(Config(0)).into())
+        .is_err());
+    drop(stale);
+    drop(current);
+
+    let cell = std::sync::Arc::into_inner(cell).unwrap();
+    let last = cell.into_inner();
+    assert_eq!(SharedTrc::atomic_count(&last), 1);
+    drop(last);
+    //100 replaced values, 2 rejected values and the final value
+    assert_eq!(DROPS.load(Ordering::SeqCst), 103);
+
+    let cell = AtomicOptionTrc::empty();
+    assert!(cell.load().is_none());
+    assert!(cell
+        .compare_and_swap(None, Some(Trc::new(1).into()))
+        .is_ok());
+    for _ in 0..20 {
+        assert_eq!(*cell.load().unwrap(), 1);
+    }
+    let shared = cell.load_shared().unwrap();
+    assert!(cell.compare_and_swap(None, None).is_err());
+    assert_eq!(*cell.swap(None).unwrap(), 1);
+    assert_eq!(SharedTrc::atomic_count(&shared), 1);
+    assert!(cell.load_shared().is_none());
+}
+
+#[test]
+fn test_atomic_cache() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    struct Config<'a>(usize, &'a AtomicUsize);
+
+    impl Drop for Config<'_> {
+        fn drop(&mut self) {
+            self.1.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    //The values do not have to be `'static`
+    let drops = AtomicUsize::new(0);
+    let cell = AtomicTrc::new(Trc::new(Config(0, &drops)).into());
+    thread::scope(|scope| {
+        for _ in 0..4 {
+            scope.spawn(|| {
+                let mut cache = cell.cache();
+                let mut last = 0;
+                for _ in 0..1000 {
+                    let config = cache.load();
+                    assert!(config.0 >= last);
+                    last = config.0;
+                    assert_eq!(Trc::local_count(config), 1);
+                }
+            });
+        }
+
+        for version in 1..=100 {
+            cell.store(Trc::new(Config(version, &drops)).into());
+        }
+    });
+    assert_eq!(drops.load(Ordering::SeqCst), 100);
+
+    //Loading the cached value does not touch the reference counts
+    let mut cache = cell.cache();
+    let first = cache.load().clone();
+    assert_eq!(Trc::atomic_count(&first), 2);
+    for _ in 0..20 {
+        let trc = cache.load();
+        assert!(Trc::ptr_eq(&first, trc));
+        assert_eq!(Trc::local_count(trc), 2);
+        assert_eq!(Trc::atomic_count(trc), 2);
+    }
+    drop(first);
+
+    //A replaced value is kept by the cache until it is loaded again
+    cell.store(Trc::new(Config(101, &drops)).into());
+    assert_eq!(drops.load(Ordering::SeqCst), 100);
+    assert_eq!(cache.load().0, 101);
+    assert_eq!(drops.load(Ordering::SeqCst), 101);
+
+    //Or until it is dropped
+    cell.store(Trc::new(Config(102, &drops)).into());
+    drop(cache);
+    assert_eq!(drops.load(Ordering::SeqCst), 102);
+
+    let cell = AtomicOptionTrc::empty();
+    let mut cache = cell.cache();
+    assert!(cache.load().is_none());
+    cell.store(Some(Trc::new(1).into()));
+    assert_eq!(**cache.load().unwrap(), 1);
+    cell.store(None);
+    assert!(cache.load().is_none());
+}
+
+#[cfg(feature = "home")]
+#[test]
+fn test_drop_home() {
+    use crate::DropHome;
+    use std::{
+        sync::{Arc, Mutex},
+        thread::ThreadId,
+    };
+
+    struct Resource(Arc<Mutex<Vec<ThreadId>>>);
+
+    impl Drop for Resource {
+        fn drop(&mut self) {
+            self.0.lock().unwrap().push(thread::current().id());
+        }
+    }
+
+    let drops = Arc::new(Mutex::new(Vec::new()));
+    let home = DropHome::new();
+    let trc: Trc<Resource> = Trc::new_with_drop_home(Resource(drops.clone()), home.clone());
+    let weak = Trc::downgrade(&trc);
+    let shared = SharedTrc::from_trc(&trc);
+    drop(trc);
+
+    let other = thread::spawn(move || {
+        let trc = SharedTrc::to_trc(shared);
+        drop(trc);
+        thread::current().id()
+    })
+    .join()
+    .unwrap();
+
+    assert!(Weak::upgrade(&weak).is_none());
+    assert!(drops.lock().unwrap().is_empty());
+    assert_eq!(home.pending(), 1);
+
+    //The queued value keeps the allocation alive until it is dropped
+    home.collect();
+    assert_eq!(home.pending(), 0);
+    assert_eq!(*drops.lock().unwrap(), [thread::current().id()]);
+    assert_ne!(other, thread::current().id());
+    drop(weak);
+
+    //Values released on the home thread are dropped immediately
+    drop(Trc::new_with_drop_home(
+        Resource(drops.clone()),
+        home.clone(),
+    ));
+    assert_eq!(home.pending(), 0);
+    assert_eq!(drops.lock().unwrap().len(), 2);
+
+    //Values whose weak references outlive the value release the home with the allocation
+    let trc = Trc::new_with_drop_home(Resource(drops.clone()), home.clone());
+    let weak = Trc::downgrade(&trc);
+    drop(trc);
+    assert_eq!(drops.lock().unwrap().len(), 3);
+    thread::spawn(move || drop(weak)).join().unwrap();
+
+    //Values left in the queue are dropped with the last handle
+    let shared = SharedTrc::from(Trc::new_with_drop_home(
+        Resource(drops.clone()),
+        home.clone(),
+    ));
+    thread::spawn(move || drop(shared)).join().unwrap();
+    assert_eq!(home.pending(), 1);
+    drop(home);
+    assert_eq!(drops.lock().unwrap().len(), 4);
+
+    //Values released on another thread after the last handle was dropped are dropped there
+    let home = DropHome::new();
+    let shared = SharedTrc::from(Trc::new_with_drop_home(
+        Resource(drops.clone()),
+        home.clone(),
+    ));
+    drop(home);
+    let other = thread::spawn(move || {
+        drop(shared);
+        thread::current().id()
+    })
+    .join()
+    .unwrap();
+    assert_eq!(drops.lock().unwrap()[4], other);
+    assert_eq!(Arc::strong_count(&drops), 1);
+}
+
+#[cfg(feature = "reclaim")]
+#[test]
+fn test_reclaim() {
+    use crate::reclaim;
+    use std::sync::{Arc, Mutex};
+
+    struct Resource(Arc<Mutex<Vec<Option<String>>>>);
+
+    impl Drop for Resource {
+        fn drop(&mut self) {
+            let name = thread::current().name().map(String::from);
+            self.0.lock().unwrap().push(name);
+        }
+    }
+
+    let drops = Arc::new(Mutex::new(Vec::new()));
+    let trc = Trc::new_deferred(Resource(drops.clone()), 1);
+    let weak = Trc::downgrade(&trc);
+    let shared = SharedTrc::from_trc(&trc);
+    drop(trc);
+
+    //The last reference is released by an upgraded `Weak<T>` on another thread
+    thread::spawn(move || {
+        let upgraded = Weak::upgrade(&weak).unwrap();
+        drop(shared);
+        drop(upgraded);
+    })
+    .join()
+    .unwrap();
+
+    reclaim::flush();
+    assert_eq!(*drops.lock().unwrap(), [Some(String::from("trc-reclaim"))]);
+
+    //Values smaller than the threshold are released inline
+    reclaim::set_threshold(1024);
+    drop(Trc::new_deferred(Resource(drops.clone()), 1023));
+    assert_eq!(drops.lock().unwrap().len(), 2);
+    assert_eq!(
+        drops.lock().unwrap()[1],
+        thread::current().name().map(String::from)
+    );
+
+    //The size of a slice is the size of its elements
+    let slice = Trc::<[Resource]>::new_deferred_slice(vec![Resource(drops.clone())]);
+    drop(slice);
+    assert_eq!(drops.lock().unwrap().len(), 3);
+
+    let slice = Trc::<[u64]>::new_deferred_slice(vec![0; 128]);
+    let weak = Trc::downgrade(&slice);
+    let shared = SharedTrc::from_trc(&slice);
+    drop(slice);
+    thread::spawn(move || drop(shared)).join().unwrap();
+    reclaim::flush();
+    assert!(Weak::upgrade(&weak).is_none());
+    drop(weak);
+    reclaim::flush();
+    reclaim::set_threshold(0);
+
+    let trc = coerce_trc!(Trc::new_deferred(vec![1, 2, 3], 24) => dyn std::any::Any);
+    assert!(trc.is::<Vec<i32>>());
+    drop(trc);
+
+    let trc = Trc::<[String]>::new_deferred_slice(vec![String::from("a"); 3]);
+    assert_eq!(*trc, ["a", "a", "a"]);
+    drop(trc);
+    reclaim::flush();
+}
+
+#[test]
+fn test_cycle_collect() {
+    use crate::{collect_cycles, CcTrc, Trace, Tracer};
+    use std::{
+        cell::{Cell, RefCell},
+        rc::Rc,
+    };
+
+    struct Node {
+        drops: Rc<Cell<usize>>,
+        edges: RefCell<Vec<CcTrc<Node>>>,
+    }
+
+    unsafe impl Trace for Node {
+        fn trace(&self, tracer: &mut Tracer<'_>) {
+            self.edges.trace(tracer);
+        }
+    }
+
+    impl Drop for Node {
+        fn drop(&mut self) {
+            self.drops.set(self.drops.get() + 1);
+        }
+    }
+
+    let drops = Rc::new(Cell::new(0));
+    let node = || {
+        CcTrc::new(Node {
+            drops: drops.clone(),
+            edges: RefCell::new(Vec::new()),
+        })
+    };
+
+    //A cycle which is still referenced from outside is kept
+    let a = node();
+    let b = node();
+    a.edges.borrow_mut().push(b.clone());
+    b.edges.borrow_mut().push(a.clone());
+    drop(b);
+    collect_cycles();
+    assert_eq!(drops.get(), 0);
+    assert_eq!(CcTrc::strong_count(&a), 2);
+    assert_eq!(CcTrc::strong_count(&a.edges.borrow()[0]), 1);
+
+    //Once it is unreachable it is collected, releasing its references to live values
+    let live = node();
+    a.edges.borrow_mut().push(live.clone());
+    assert_eq!(CcTrc::strong_count(&live), 2);
+    drop(a);
+    assert_eq!(drops.get(), 0);
+    collect_cycles();
+    assert_eq!(drops.get(), 2);
+    assert_eq!(CcTrc::strong_count(&live), 1);
+    drop(live);
+    assert_eq!(drops.get(), 3);
+
+    //Long cycles do not overflow the stack
+    drops.set(0);
+    let first = node();
+    let mut last = first.clone();
+    for _ in 0..100_000 {
+        let next = node();
+        last.edges.borrow_mut().push(next.clone());
+        last = next;
+    }
+    last.edges.borrow_mut().push(first);
+    drop(last);
+    collect_cycles();
+    assert_eq!(drops.get(), 100_001);
+}
+
+#[test]
+fn test_cycle_resurrect() {
+    use crate::{collect_cycles, CcTrc, Trace, Tracer};
+    use std::{cell::RefCell, panic};
+
+    thread_local! {
+        static STASH: RefCell<Vec<CcTrc<Node>>> = const { RefCell::new(Vec::new()) };
+    }
+
+    struct Node(RefCell<Option<CcTrc<Node>>>);
+
+    unsafe impl Trace for Node {
+        fn trace(&self, tracer: &mut Tracer<'_>) {
+            self.0.trace(tracer);
+        }
+    }
+
+    impl Drop for Node {
+        fn drop(&mut self) {
+            if let Some(next) = self.0.get_mut().take() {
+                STASH.with(|stash| stash.borrow_mut().push(next));
+            }
+        }
+    }
+
+    let node = CcTrc::new(Node(RefCell::new(None)));
+    *node.0.borrow_mut() = Some(node.clone());
+    drop(node);
+    collect_cycles();
+
+    //The destructor moved a `CcTrc` to the collected value out of the cycle
+    let stashed = STASH.with(|stash| stash.borrow_mut().pop()).unwrap();
+    assert_eq!(CcTrc::strong_count(&stashed), 1);
+    assert!(panic::catch_unwind(panic::AssertUnwindSafe(|| stashed.clone())).is_err());
+    assert!(panic::catch_unwind(panic::AssertUnwindSafe(|| stashed.0.borrow().is_none())).is_err());
+    drop(stashed);
+    collect_cycles();
+}
+
+#[cfg(feature = "derive")]
+#[test]
+fn test_cycle_derive() {
+    use crate::{collect_cycles, CcTrc, Trace};
+    use std::{
+        cell::{Cell, RefCell},
+        rc::Rc,
+    };
+
+    #[derive(Trace)]
+    struct Subject {
+        #[trace(skip)]
+        drops: Rc<Cell<usize>>,
+        observers: RefCell<Vec<Observer<u8>>>,
+    }
+
+    #[derive(Trace)]
+    enum Observer<T> {
+        Empty,
+        Watching(T, CcTrc<Subject>),
+        Named {
+            name: String,
+            subject: CcTrc<Subject>,
+        },
+    }
+
+    impl Drop for Subject {
+        fn drop(&mut self) {
+            self.drops.set(self.drops.get() + 1);
+        }
+    }
+
+    let drops = Rc::new(Cell::new(0));
+    let subject = CcTrc::new(Subject {
+        drops: drops.clone(),
+        observers: RefCell::new(vec![Observer::Empty]),
+    });
+    subject
+        .observers
+        .borrow_mut()
+        .push(Observer::Watching(1, subject.clone()));
+    subject.observers.borrow_mut().push(Observer::Named {
+        name: String::from("observer"),
+        subject: subject.clone(),
+    });
+    assert_eq!(CcTrc::strong_count(&subject), 3);
+
+    drop(subject);
+    assert_eq!(drops.get(), 0);
+    collect_cycles();
+    assert_eq!(drops.get(), 1);
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_serde() {
+    use serde::{de::value::SeqDeserializer, Deserialize};
+
+    let trc = Trc::new(vec![1, 2, 3]);
+    assert_eq!(serde_json::to_string(&trc).unwrap(), "[1,2,3]");
+    let shared = SharedTrc::from_trc(&trc);
+    assert_eq!(serde_json::to_string(&shared).unwrap(), "[1,2,3]");
+
+    let weak = Trc::downgrade(&trc);
+    assert_eq!(serde_json::to_string(&weak).unwrap(), "[1,2,3]");
+    drop((trc, shared));
+    assert_eq!(serde_json::to_string(&weak).unwrap(), "null");
+
+    let trc: Trc<Vec<i32>> = serde_json::from_str("[4,5]").unwrap();
+    assert_eq!(*trc, [4, 5]);
+    let shared: SharedTrc<i32> = serde_json::from_str("6").unwrap();
+    assert_eq!(*shared, 6);
+
+    let string: Trc<str> = serde_json::from_str("\"text\"").unwrap();
+    assert_eq!(&*string, "text");
+    let string: SharedTrc<str> = serde_json::from_str("\"escaped\\n\"").unwrap();
+    assert_eq!(&*string, "escaped\n");
+    assert!(serde_json::from_str::<Trc<str>>("1").is_err());
+
+    let slice: Trc<[String]> = serde_json::from_str("[\"a\",\"b\"]").unwrap();
+    assert_eq!(*slice, ["a", "b"]);
+    let slice: SharedTrc<[u8]> = serde_json::from_str("[]").unwrap();
+    assert!(slice.is_empty());
+    assert!(serde_json::from_str::<Trc<[u8]>>("[1,-1]").is_err());
+
+    //Formats which report the length are deserialized directly, and the length is checked
+    struct Reported<I>(I, usize);
+
+    impl<I: Iterator> Iterator for Reported<I> {
+        type Item = I::Item;
+
+        fn next(&mut self) -> Option<I::Item> {
+            self.0.next()
+        }
+
+        fn size_hint(&self) -> (usize, Option<usize>) {
+            (self.1, Some(self.1))
+        }
+    }
+
+    for reported in [0, 2, 3, 5] {
+        let elems = (0..3).map(|i| i.to_string());
+        let deserializer =
+            SeqDeserializer::<_, serde::de::value::Error>::new(Reported(elems, reported));
+        let slice = Trc::<[String]>::deserialize(deserializer).unwrap();
+        assert_eq!(*slice, ["0", "1", "2"]);
+    }
+
+    //JSON does not report the length, so the allocation grows in place instead of collecting into a `Vec` first
+    let json = serde_json::to_string(&(0..1000).collect::<Vec<u64>>()).unwrap();
+    let before = counting::get();
+    let slice: Trc<[u64]> = serde_json::from_str(&json).unwrap();
+    let after = counting::get();
+    assert!(slice.iter().copied().eq(0..1000));
+    assert_eq!(after.allocs - before.allocs, 1);
+    assert!(after.reallocs > before.reallocs);
+    assert_eq!(after.deallocs, before.deallocs);
+}
+
+/// Global allocator which counts the allocations made by each thread.
+#[cfg(feature = "serde")]
+mod counting {
+    use std::{
+        alloc::{GlobalAlloc, Layout, System},
+        cell::Cell,
+    };
+
+    #[derive(Clone, Copy, Default)]
+    pub struct Counts {
+        pub allocs: usize,
+        pub reallocs: usize,
+        pub deallocs: usize,
+    }
+
+    thread_local! {
+        static COUNTS: Cell<Counts> = const { Cell::new(Counts { allocs: 0, reallocs: 0, deallocs: 0 }) };
+    }
+
+    /// Get the counts of the current thread.
+    pub fn get() -> Counts {
+        COUNTS.with(Cell::get)
+    }
+
+    fn count(f: impl FnOnce(&mut Counts)) {
+        let _ = COUNTS.try_with(|counts| {
+            let mut value = counts.get();
+            f(&mut value);
+            counts.set(value);
+        });
+    }
+
+    struct Counting;
+
+    unsafe impl GlobalAlloc for Counting {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            count(|counts| counts.allocs += 1);
+            System.alloc(layout)
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            count(|counts| counts.deallocs += 1);
+            System.dealloc(ptr, layout)
+        }
+
+        unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+            count(|counts| counts.reallocs += 1);
+            System.realloc(ptr, layout, new_size)
+        }
+    }
+
+    #[global_allocator]
+    static GLOBAL: Counting = Counting;
+}
+
+#[test]
+fn test_get_mut_downgrade_race() {
+    use std::sync::{Arc, Barrier};
+
+    //`get_mut` locks the weak count, which a concurrent downgrade must not wrap around
+    let mut shared: SharedTrc<i32> = Trc::new(100).into();
+    let other = shared.clone();
+    let barrier = Arc::new(Barrier::new(2));
+    let handle = thread::spawn({
+        let barrier = barrier.clone();
+        move || {
+            let trc = SharedTrc::to_trc(other.clone());
+            barrier.wait();
+            for _ in 0..100_000 {
+                drop(SharedTrc::downgrade(&other));
+                drop(Trc::downgrade(&trc));
+            }
+        }
+    });
+    barrier.wait();
+    for _ in 0..200_000 {
+        let _ = SharedTrc::get_mut(&mut shared);
+    }
+    handle.join().unwrap();
+
+    assert_eq!(SharedTrc::weak_count(&shared), 1);
+    assert_eq!(*SharedTrc::get_mut(&mut shared).unwrap(), 100);
+}
@@ -0,0 +1,524 @@
+//! `ThinTrc<H, [T]>` and `ThinTrc<H, str>`, a `Trc` to a header and a slice which stores the length of the slice in
+//! the allocation.
+
+use std::{
+    borrow::Borrow,
+    fmt::{Debug, Display},
+    hash::{Hash, Hasher},
+    marker::PhantomData,
+    mem::ManuallyDrop,
+    ops::Deref,
+    ptr::{self, addr_of, slice_from_raw_parts_mut, NonNull},
+};
+
+use allocator_api2::alloc::Global;
+
+use crate::{create_from_iterator_exact, HeaderSlice, SharedTrcInternal, Trc};
+
+/// Header of the data of a `ThinTrc<H, S>`. The length of the slice is stored in it, so that it can be read through
+/// a thin pointer.
+pub(crate) struct ThinHeader<H> {
+    header: H,
+    len: usize,
+}
+
+/// Data of a `ThinTrc<H, S>`, which holds the elements of `S` as a slice.
+type ThinData<H, T> = HeaderSlice<ThinHeader<H>, [T]>;
+
+/// Build the wide pointer to a `SharedTrcInternal<ThinData<H, T>>` from a thin pointer to it.
+///
+/// # Safety
+/// `ptr` must point to a `SharedTrcInternal<ThinData<H, T>>` whose length is initialized.
+#[inline]
+unsafe fn fat_ptr<H, T>(ptr: NonNull<u8>) -> NonNull<SharedTrcInternal<ThinData<H, T>>> {
+    //The offset of the length does not depend on the length of the slice
+    let empty = slice_from_raw_parts_mut(ptr.as_ptr() as *mut T, 0)
+        as *mut SharedTrcInternal<ThinData<H, T>>;
+    let len = *addr_of!((*empty).data.header.len);
+
+    let res = slice_from_raw_parts_mut(ptr.as_ptr() as *mut T, len)
+        as *mut SharedTrcInternal<ThinData<H, T>>;
+    NonNull::new_unchecked(res)
+}
+
+mod sealed {
+    /// Slice types which a `ThinTrc<H, S>` can hold: `[T]` and `str`.
+    pub trait ThinSlice {
+        /// Type of the elements which are stored in the allocation.
+        type Elem;
+
+        /// View the elements stored in the allocation as `Self`.
+        ///
+        /// # Safety
+        /// The elements must have been created from a valid `Self`.
+        unsafe fn from_elems(elems: &[Self::Elem]) -> &Self;
+    }
+
+    impl<T> ThinSlice for [T] {
+        type Elem = T;
+
+        #[inline]
+        unsafe fn from_elems(elems: &[T]) -> &[T] {
+            elems
+        }
+    }
+
+    impl ThinSlice for str {
+        type Elem = u8;
+
+        #[inline]
+        unsafe fn from_elems(elems: &[u8]) -> &str {
+            std::str::from_utf8_unchecked(elems)
+        }
+    }
+}
+
+/// `ThinTrc<H, S>` is a [`Trc`] to a header `H` and a slice `S`, which is `[T]` or `str`, stored in the same
+/// allocation. Unlike `Trc<[T]>` and `Trc<str>`, the length of the slice is stored in the allocation instead of in the
+/// pointer, so the pointer to the allocation is thin. The local thread reference count is the one stored in the
+/// allocation by the creating thread, so a `ThinTrc<H, S>` is a single word wide, compared to three words for a
+/// `Trc<[T]>`.
+///
+/// `ThinTrc<H, S>` dereferences to the slice, and the header is accessed with [`ThinTrc::header`]. If no header is
+/// needed, `H` can be `()`. A `ThinTrc` is not [`Send`] or [`Sync`], and cannot be converted into a [`SharedTrc`](crate::SharedTrc),
+/// so it never leaves the thread which created the allocation. To use the data on another thread, convert it into a
+/// `Trc<[T]>` or `Trc<str>`.
+///
+/// ## Examples
+/// ```
+/// use trc::ThinTrc;
+///
+/// let thin = ThinTrc::from_header_and_iter("children", vec![1, 2, 3]);
+/// assert_eq!(*ThinTrc::header(&thin), "children");
+/// assert_eq!(*thin, [1, 2, 3]);
+/// assert_eq!(std::mem::size_of_val(&thin), std::mem::size_of::<usize>());
+///
+/// let name = ThinTrc::<(), str>::from("name");
+/// assert_eq!(&*name, "name");
+/// ```
+pub struct ThinTrc<H, S: ?Sized + sealed::ThinSlice> {
+    shared: NonNull<u8>,
+    phantom: PhantomData<Trc<HeaderSlice<ThinHeader<H>, S>>>,
+}
+
+impl<H, T> ThinTrc<H, [T]> {
+    /// Create a new `ThinTrc<H, [T]>` from a header and the elements of an iterator with an exact size, in a single
+    /// allocation. Panics if the iterator yields a different number of elements than its `len`.
+    ///
+    /// # Examples
+    /// ```
+    /// use trc::ThinTrc;
+    ///
+    /// let thin = ThinTrc::from_header_and_iter(3, (1..4).map(|x| x * 10));
+    /// assert_eq!(*ThinTrc::header(&thin), 3);
+    /// assert_eq!(*thin, [10, 20, 30]);
+    /// ```
+    pub fn from_header_and_iter<I>(header: H, iter: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        I::IntoIter: ExactSizeIterator,
+    {
+        let iterator = iter.into_iter();
+        let header = ThinHeader {
+            header,
+            len: iterator.len(),
+        };
+
+        Self::from_trc(Trc::from_header_and_iter(header, iterator))
+    }
+}
+
+impl<H> ThinTrc<H, str> {
+    /// Create a new `ThinTrc<H, str>` from a header and a copy of a string, in a single allocation.
+    ///
+    /// # Examples
+    /// ```
+    /// use trc::ThinTrc;
+    ///
+    /// let thin = ThinTrc::from_header_and_str(1, "ident");
+    /// assert_eq!(*ThinTrc::header(&thin), 1);
+    /// assert_eq!(&*thin, "ident");
+    /// ```
+    pub fn from_header_and_str(header: H, value: &str) -> Self {
+        let header = ThinHeader {
+            header,
+            len: value.len(),
+        };
+
+        Self::from_trc(Trc::from_header_and_iter(
+            header,
+            value.as_bytes().iter().copied(),
+        ))
+    }
+}
+
+impl<H, S: ?Sized + sealed::ThinSlice> ThinTrc<H, S> {
+    /// Get an immutable reference to the header.
+    ///
+    /// # Examples
+    /// ```
+    /// use trc::ThinTrc;
+    ///
+    /// let thin = ThinTrc::from_header_and_iter(String::from("header"), [1, 2]);
+    /// assert_eq!(ThinTrc::header(&thin), "header");
+    /// ```
+    #[inline]
+    pub fn header(this: &Self) -> &H {
+        &unsafe { this.as_data() }.header.header
+    }
+
+    /// Return the local thread reference count of the object, which is how many `ThinTrc<H, S>`s point to the data.
+    ///
+    /// # Examples
+    /// ```
+    /// use trc::ThinTrc;
+    ///
+    /// let thin = ThinTrc::from(&[1, 2, 3][..]);
+    /// let thin2 = thin.clone();
+    /// assert_eq!(ThinTrc::local_count(&thin), 2);
+    /// ```
+    #[inline]
+    pub fn local_count(this: &Self) -> usize {
+        Trc::local_count(&this.as_trc())
+    }
+
+    /// Return the atomic reference count of the object, which is how many threads are using the data. A `ThinTrc`
+    /// never leaves its thread, so this is always 1.
+    ///
+    /// # Examples
+    /// ```
+    /// use trc::ThinTrc;
+    ///
+    /// let thin = ThinTrc::from(&[1, 2, 3][..]);
+    /// assert_eq!(ThinTrc::atomic_count(&thin), 1);
+    /// ```
+    #[inline]
+    pub fn atomic_count(this: &Self) -> usize {
+        Trc::atomic_count(&this.as_trc())
+    }
+
+    /// Checks if the other `ThinTrc<H, S>` is equal to this one according to their internal pointers.
+    ///
+    /// # Examples
+    /// ```
+    /// use trc::ThinTrc;
+    ///
+    /// let thin1 = ThinTrc::from(&[1, 2, 3][..]);
+    /// let thin2 = thin1.clone();
+    /// assert!(ThinTrc::ptr_eq(&thin1, &thin2));
+    /// ```
+    #[inline]
+    pub fn ptr_eq(this: &Self, other: &Self) -> bool {
+        this.shared == other.shared
+    }
+
+    /// Get a reference to the data of the allocation.
+    ///
+    /// # Safety
+    /// The returned reference must not outlive `self`.
+    #[inline]
+    unsafe fn as_data<'a>(&self) -> &'a ThinData<H, S::Elem> {
+        &(*fat_ptr::<H, S::Elem>(self.shared).as_ptr()).data
+    }
+
+    /// View this `ThinTrc<H, S>` as the `Trc` it was created from, without changing any reference counts.
+    #[inline]
+    fn as_trc(&self) -> ManuallyDrop<Trc<ThinData<H, S::Elem>>> {
+        ManuallyDrop::new(unsafe { Trc::from_inner_inline(fat_ptr(self.shared), Global) })
+    }
+
+    /// Convert a `Trc` to its data into a `ThinTrc<H, S>`, keeping all reference counts. The `Trc` must use the local
+    /// thread reference count stored in the allocation.
+    #[inline]
+    fn from_trc(trc: Trc<ThinData<H, S::Elem>>) -> Self {
+        debug_assert!(Trc::is_inline_threadref(&trc));
+
+        let trc = ManuallyDrop::new(trc);
+        ThinTrc {
+            shared: trc.shared.cast(),
+            phantom: PhantomData,
+        }
+    }
+
+    /// Check if this is the only reference to the allocation, so its elements can be moved out.
+    #[inline]
+    fn is_unique(this: &Self) -> bool {
+        Trc::get_mut(&mut this.as_trc()).is_some()
+    }
+
+    /// Free the allocation of this `ThinTrc<H, S>` and drop its header, without dropping its elements.
+    ///
+    /// # Safety
+    /// This must be the only reference to the allocation, and the elements must have been moved out.
+    #[inline]
+    unsafe fn forget_elems(this: Self) {
+        let this = ManuallyDrop::new(this);
+        drop(
+            Trc::<ThinData<H, ManuallyDrop<S::Elem>>>::from_inner_inline(
+                fat_ptr(this.shared),
+                Global,
+            ),
+        );
+    }
+}
+
+impl<H, S: ?Sized + sealed::ThinSlice> Deref for ThinTrc<H, S> {
+    type Target = S;
+
+    /// Get an immutable reference to the slice.
+    ///
+    /// # Examples
+    /// ```
+    /// use trc::ThinTrc;
+    ///
+    /// let thin = ThinTrc::from(vec![1, 2, 3]);
+    /// assert_eq!(thin.len(), 3);
+    /// ```
+    #[inline]
+    fn deref(&self) -> &S {
+        unsafe { S::from_elems(&self.as_data().slice) }
+    }
+}
+
+impl<H, S: ?Sized + sealed::ThinSlice> Drop for ThinTrc<H, S> {
+    #[inline]
+    fn drop(&mut self) {
+        drop(ManuallyDrop::into_inner(self.as_trc()));
+    }
+}
+
+impl<H, S: ?Sized + sealed::ThinSlice> Clone for ThinTrc<H, S> {
+    /// Clone a `ThinTrc<H, S>` (increment it's local reference count).
+    /// It will panic if the local reference count overflows.
+    /// ```
+    /// use trc::ThinTrc;
+    ///
+    /// let thin = ThinTrc::from(&[1, 2, 3][..]);
+    /// let thin2 = thin.clone();
+    /// assert_eq!(ThinTrc::local_count(&thin), ThinTrc::local_count(&thin2));
+    /// ```
+    #[inline]
+    fn clone(&self) -> Self {
+        Self::from_trc(Trc::clone(&self.as_trc()))
+    }
+}
+
+impl<H, S: ?Sized + sealed::ThinSlice> AsRef<S> for ThinTrc<H, S> {
+    fn as_ref(&self) -> &S {
+        self
+    }
+}
+
+impl<H, S: ?Sized + sealed::ThinSlice> Borrow<S> for ThinTrc<H, S> {
+    fn borrow(&self) -> &S {
+        self
+    }
+}
+
+impl<H: Debug, S: ?Sized + sealed::ThinSlice + Debug> Debug for ThinTrc<H, S> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("ThinTrc")
+            .field("header", Self::header(self))
+            .field("slice", &&**self)
+            .finish()
+    }
+}
+
+impl<H, S: ?Sized + sealed::ThinSlice + Display> Display for ThinTrc<H, S> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        Display::fmt(&**self, f)
+    }
+}
+
+impl<H: PartialEq, S: ?Sized + sealed::ThinSlice + PartialEq> PartialEq for ThinTrc<H, S> {
+    /// Equality by value for the header and the slice.
+    ///
+    /// # Examples
+    /// ```
+    /// use trc::ThinTrc;
+    ///
+    /// let thin1 = ThinTrc::from_header_and_iter(1, [2, 3]);
+    /// let thin2 = ThinTrc::from_header_and_iter(1, [2, 3]);
+    /// assert!(thin1 == thin2);
+    /// ```
+    #[inline]
+    fn eq(&self, other: &Self) -> bool {
+        ThinTrc::header(self) == ThinTrc::header(other) && **self == **other
+    }
+}
+
+impl<H: Eq, S: ?Sized + sealed::ThinSlice + Eq> Eq for ThinTrc<H, S> {}
+
+impl<H: Hash, S: ?Sized + sealed::ThinSlice + Hash> Hash for ThinTrc<H, S> {
+    fn hash<HS: Hasher>(&self, state: &mut HS) {
+        ThinTrc::header(self).hash(state);
+        (**self).hash(state);
+    }
+}
+
+impl<T: Clone> From<&[T]> for ThinTrc<(), [T]> {
+    /// Create a new `ThinTrc<(), [T]>` by cloning the elements of a slice.
+    ///
+    /// # Examples
+    /// ```
+    /// use trc::ThinTrc;
+    ///
+    /// let thin = ThinTrc::from(&[1, 2, 3][..]);
+    /// assert_eq!(*thin, [1, 2, 3]);
+    /// ```
+    #[inline]
+    fn from(value: &[T]) -> Self {
+        ThinTrc::from_header_and_iter((), value.iter().cloned())
+    }
+}
+
+impl<T> From<Vec<T>> for ThinTrc<(), [T]> {
+    /// Create a new `ThinTrc<(), [T]>` by moving the elements of a [`Vec`].
+    ///
+    /// # Examples
+    /// ```
+    /// use trc::ThinTrc;
+    ///
+    /// let thin = ThinTrc::from(vec![String::from("a"), String::from("b")]);
+    /// assert_eq!(*thin, ["a", "b"]);
+    /// ```
+    #[inline]
+    fn from(value: Vec<T>) -> Self {
+        ThinTrc::from_header_and_iter((), value)
+    }
+}
+
+impl<T: Clone> From<Trc<[T]>> for ThinTrc<(), [T]> {
+    /// Convert a `Trc<[T]>` into a `ThinTrc<(), [T]>`. The length must be stored in the allocation, so a new one is
+    /// created. If this is the only reference to the elements, they are moved into it. Otherwise, other references
+    /// still use the elements, so they are cloned.
+    ///
+    /// # Examples
+    /// ```
+    /// use trc::{ThinTrc, Trc};
+    ///
+    /// let trc = Trc::<[String]>::from(vec![String::from("a")]);
+    /// let ptr = trc[0].as_ptr();
+    /// let thin = ThinTrc::from(trc);
+    /// assert_eq!(thin[0].as_ptr(), ptr);
+    /// ```
+    fn from(mut value: Trc<[T]>) -> Self {
+        if Trc::get_mut(&mut value).is_none() {
+            return ThinTrc::from(&*value);
+        }
+
+        let thin =
+            ThinTrc::from_header_and_iter((), value.iter().map(|elem| unsafe { ptr::read(elem) }));
+
+        //The elements were moved, so the old allocation is freed without dropping them
+        let value = ManuallyDrop::new(value);
+        drop(Trc {
+            shared: unsafe {
+                NonNull::new_unchecked(
+                    value.shared.as_ptr() as *mut SharedTrcInternal<[ManuallyDrop<T>]>
+                )
+            },
+            threadref: value.threadref,
+            alloc: Global,
+        });
+        thin
+    }
+}
+
+impl<H, T: Clone> From<ThinTrc<H, [T]>> for Trc<[T]> {
+    /// Convert a `ThinTrc<H, [T]>` into a `Trc<[T]>`, dropping the header. The length must be stored in the pointer,
+    /// so a new allocation is created. If this is the only reference to the elements, they are moved into it.
+    /// Otherwise, other references still use the elements, so they are cloned.
+    ///
+    /// # Examples
+    /// ```
+    /// use trc::{ThinTrc, Trc};
+    ///
+    /// let thin = ThinTrc::from_header_and_iter("header", [String::from("a")]);
+    /// let ptr = thin[0].as_ptr();
+    /// let trc: Trc<[String]> = Trc::from(thin);
+    /// assert_eq!(trc[0].as_ptr(), ptr);
+    /// ```
+    fn from(value: ThinTrc<H, [T]>) -> Self {
+        if !ThinTrc::is_unique(&value) {
+            return Trc::from(&*value);
+        }
+
+        let shared = create_from_iterator_exact(
+            value.iter().map(|elem| unsafe { ptr::read(elem) }),
+            &Global,
+        );
+        unsafe {
+            ThinTrc::forget_elems(value);
+            Trc::from_inner_inline(shared, Global)
+        }
+    }
+}
+
+impl From<&str> for ThinTrc<(), str> {
+    /// Create a new `ThinTrc<(), str>` by copying a string.
+    ///
+    /// # Examples
+    /// ```
+    /// use trc::ThinTrc;
+    ///
+    /// let thin = ThinTrc::<(), str>::from("str");
+    /// assert_eq!(&*thin, "str");
+    /// ```
+    #[inline]
+    fn from(value: &str) -> Self {
+        ThinTrc::from_header_and_str((), value)
+    }
+}
+
+impl From<String> for ThinTrc<(), str> {
+    /// Create a new `ThinTrc<(), str>` by copying the bytes of a [`String`].
+    ///
+    /// # Examples
+    /// ```
+    /// use trc::ThinTrc;
+    ///
+    /// let thin = ThinTrc::<(), str>::from(String::from("str"));
+    /// assert_eq!(&*thin, "str");
+    /// ```
+    #[inline]
+    fn from(value: String) -> Self {
+        ThinTrc::from_header_and_str((), &value)
+    }
+}
+
+impl From<Trc<str>> for ThinTrc<(), str> {
+    /// Convert a `Trc<str>` into a `ThinTrc<(), str>`. The length must be stored in the allocation, so the bytes are
+    /// copied into a new one.
+    ///
+    /// # Examples
+    /// ```
+    /// use trc::{ThinTrc, Trc};
+    ///
+    /// let trc = Trc::<str>::from("str");
+    /// let thin = ThinTrc::from(trc);
+    /// assert_eq!(&*thin, "str");
+    /// ```
+    #[inline]
+    fn from(value: Trc<str>) -> Self {
+        ThinTrc::from_header_and_str((), &value)
+    }
+}
+
+impl<H> From<ThinTrc<H, str>> for Trc<str> {
+    /// Convert a `ThinTrc<H, str>` into a `Trc<str>`, dropping the header. The length must be stored in the pointer,
+    /// so the bytes are copied into a new allocation.
+    ///
+    /// # Examples
+    /// ```
+    /// use trc::{ThinTrc, Trc};
+    ///
+    /// let thin = ThinTrc::from_header_and_str("header", "str");
+    /// let trc: Trc<str> = Trc::from(thin);
+    /// assert_eq!(&*trc, "str");
+    /// ```
+    #[inline]
+    fn from(value: ThinTrc<H, str>) -> Self {
+        Trc::from(&*value)
+    }
+}
@@ -0,0 +1,304 @@
+//! `UniqueTrc<T>`, an exclusively owned allocation which can be mutated and then frozen into a `Trc<T>`.
+
+use std::{
+    alloc::Layout,
+    cell::UnsafeCell,
+    fmt::{Debug, Display},
+    mem::ManuallyDrop,
+    ops::{Deref, DerefMut},
+    ptr::{self, addr_of_mut, write, NonNull},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use allocator_api2::alloc::{Allocator, Global};
+
+use crate::{
+    allocate_for_layout, sum_value, SharedTrc, SharedTrcInternal, Trc, Weak, MAX_REFCOUNT,
+};
+
+/// `UniqueTrc<T>` owns an allocation for a [`Trc<T>`] exclusively, so it implements [`DerefMut`]. It can hand out
+/// [`Weak<T>`]s with [`UniqueTrc::downgrade`], which cannot be upgraded until the `UniqueTrc<T>` is frozen into a
+/// `Trc<T>` with [`UniqueTrc::into_trc`] or into a [`SharedTrc<T>`] with [`UniqueTrc::into_shared`].
+///
+/// Unlike [`Trc::new_cyclic`], building the value can be split across multiple steps and functions, which makes it
+/// possible to build graphs whose nodes refer back to each other.
+///
+/// To prevent name clashes, `UniqueTrc<T>`'s functions are associated.
+///
+/// ## Examples
+/// ```
+/// use trc::{UniqueTrc, Weak};
+///
+/// struct Node {
+///     parent: Weak<Node>,
+///     children: Vec<Weak<Node>>,
+/// }
+///
+/// let mut root = UniqueTrc::new(Node { parent: Weak::new(), children: Vec::new() });
+/// let child = UniqueTrc::new(Node { parent: UniqueTrc::downgrade(&root), children: Vec::new() });
+/// assert!(Weak::upgrade(&child.parent).is_none());
+///
+/// let child = UniqueTrc::into_trc(child);
+/// root.children.push(trc::Trc::downgrade(&child));
+/// let root = UniqueTrc::into_trc(root);
+///
+/// assert!(trc::Trc::ptr_eq(&Weak::upgrade(&child.parent).unwrap(), &root));
+/// ```
+pub struct UniqueTrc<T: ?Sized, A: Allocator = Global> {
+    shared: NonNull<SharedTrcInternal<T>>,
+    alloc: A,
+}
+
+unsafe impl<T: ?Sized + Sync + Send, A: Allocator + Send> Send for UniqueTrc<T, A> {}
+unsafe impl<T: ?Sized + Sync + Send, A: Allocator + Sync> Sync for UniqueTrc<T, A> {}
+
+impl<T> UniqueTrc<T> {
+    /// Creates a new `UniqueTrc<T>` from the provided data.
+    ///
+    /// # Examples
+    /// ```
+    /// use trc::UniqueTrc;
+    ///
+    /// let mut unique = UniqueTrc::new(100);
+    /// *unique += 1;
+    /// assert_eq!(*unique, 101);
+    /// ```
+    #[inline]
+    pub fn new(value: T) -> Self {
+        Self::new_in(value, Global)
+    }
+}
+
+impl<T, A: Allocator> UniqueTrc<T, A> {
+    /// Creates a new `UniqueTrc<T, A>` from the provided data, in the provided allocator.
+    ///
+    /// # Examples
+    /// ```
+    /// # #![cfg_attr(feature = "nightly", feature(allocator_api))]
+    /// use trc::UniqueTrc;
+    /// use allocator_api2::alloc::Global;
+    ///
+    /// let unique = UniqueTrc::new_in(100, Global);
+    /// assert_eq!(*unique, 100);
+    /// ```
+    #[inline]
+    pub fn new_in(value: T, alloc: A) -> Self {
+        let layout = Layout::new::<SharedTrcInternal<T>>();
+        let shared = allocate_for_layout(layout, &alloc).cast::<SharedTrcInternal<T>>();
+
+        //The atomic reference count stays zero until the value is frozen, so `Weak<T>`s cannot be upgraded
+        unsafe {
+            write(
+                shared.as_ptr(),
+                SharedTrcInternal {
+                    atomicref: AtomicUsize::new(0),
+                    weakcount: AtomicUsize::new(1),
+                    threadref: UnsafeCell::new(1),
+                    #[cfg(feature = "reclaim")]
+                    reclaim: None,
+                    #[cfg(feature = "home")]
+                    home: None,
+                    data: value,
+                },
+            )
+        };
+
+        UniqueTrc { shared, alloc }
+    }
+}
+
+impl<T: ?Sized, A: Allocator + Clone> UniqueTrc<T, A> {
+    /// Create a `Weak<T>` to the data. It cannot be upgraded until this `UniqueTrc<T>` is frozen into a `Trc<T>` or
+    /// `SharedTrc<T>`.
+    ///
+    /// # Examples
+    /// ```
+    /// use trc::{UniqueTrc, Weak};
+    ///
+    /// let unique = UniqueTrc::new(100);
+    /// let weak = UniqueTrc::downgrade(&unique);
+    /// assert!(Weak::upgrade(&weak).is_none());
+    ///
+    /// let trc = UniqueTrc::into_trc(unique);
+    /// assert_eq!(*Weak::upgrade(&weak).unwrap(), 100);
+    /// ```
+    #[inline]
+    pub fn downgrade(this: &Self) -> Weak<T, A> {
+        let prev = sum_value(
+            &unsafe { this.shared.as_ref() }.weakcount,
+            1,
+            Ordering::Acquire,
+        );
+        if prev > MAX_REFCOUNT {
+            panic!("Overflow of maximum weak reference count.");
+        }
+
+        Weak {
+            data: this.shared,
+            alloc: this.alloc.clone(),
+        }
+    }
+}
+
+impl<T: ?Sized, A: Allocator> UniqueTrc<T, A> {
+    /// Freeze this `UniqueTrc<T>` into a `Trc<T>`. `Weak<T>`s created from it can be upgraded afterwards.
+    ///
+    /// # Examples
+    /// ```
+    /// use trc::{Trc, UniqueTrc};
+    ///
+    /// let mut unique = UniqueTrc::new(vec![1]);
+    /// unique.push(2);
+    /// let trc = UniqueTrc::into_trc(unique);
+    /// assert_eq!(*trc, [1, 2]);
+    /// assert_eq!(Trc::local_count(&trc), 1);
+    /// ```
+    #[inline]
+    pub fn into_trc(this: Self) -> Trc<T, A> {
+        let (shared, alloc) = UniqueTrc::freeze(this);
+
+        unsafe { Trc::from_inner_inline(shared, alloc) }
+    }
+
+    /// Freeze this `UniqueTrc<T>` into a `SharedTrc<T>`, which can be sent to other threads. `Weak<T>`s created from
+    /// it can be upgraded afterwards.
+    ///
+    /// # Examples
+    /// ```
+    /// use std::thread;
+    /// use trc::{SharedTrc, UniqueTrc};
+    ///
+    /// let mut unique = UniqueTrc::new(100);
+    /// *unique += 1;
+    /// let shared = UniqueTrc::into_shared(unique);
+    ///
+    /// let handle = thread::spawn(move || {
+    ///     let trc = SharedTrc::to_trc(shared);
+    ///     assert_eq!(*trc, 101);
+    /// });
+    /// handle.join().unwrap();
+    /// ```
+    #[inline]
+    pub fn into_shared(this: Self) -> SharedTrc<T, A> {
+        let (data, alloc) = UniqueTrc::freeze(this);
+
+        SharedTrc { data, alloc }
+    }
+
+    /// Give the allocation one atomic reference, which is returned along with the allocator.
+    #[inline]
+    fn freeze(this: Self) -> (NonNull<SharedTrcInternal<T>>, A) {
+        let this = ManuallyDrop::new(this);
+        let alloc = unsafe { ptr::read(&this.alloc) };
+
+        //Synchronizes with the upgrade of a `Weak<T>`, so that the writes through this `UniqueTrc<T>` are visible
+        unsafe { this.shared.as_ref() }
+            .atomicref
+            .store(1, Ordering::Release);
+
+        (this.shared, alloc)
+    }
+}
+
+impl<T: ?Sized, A: Allocator> Deref for UniqueTrc<T, A> {
+    type Target = T;
+
+    /// Get an immutable reference to the internal data.
+    ///
+    /// # Examples
+    /// ```
+    /// use trc::UniqueTrc;
+    ///
+    /// let unique = UniqueTrc::new(100i32);
+    /// assert_eq!(*unique, 100i32);
+    /// ```
+    #[inline]
+    fn deref(&self) -> &T {
+        &unsafe { self.shared.as_ref() }.data
+    }
+}
+
+impl<T: ?Sized, A: Allocator> DerefMut for UniqueTrc<T, A> {
+    /// Get a mutable reference to the internal data. This is sound because no `Trc<T>`, `SharedTrc<T>` or upgradable
+    /// `Weak<T>` exists while the `UniqueTrc<T>` does.
+    ///
+    /// # Examples
+    /// ```
+    /// use trc::UniqueTrc;
+    ///
+    /// let mut unique = UniqueTrc::new(100i32);
+    /// *unique = 200;
+    /// assert_eq!(*unique, 200i32);
+    /// ```
+    #[inline]
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut (*self.shared.as_ptr()).data }
+    }
+}
+
+impl<T: ?Sized, A: Allocator> Drop for UniqueTrc<T, A> {
+    #[inline]
+    fn drop(&mut self) {
+        unsafe { ptr::drop_in_place(addr_of_mut!((*self.shared.as_ptr()).data)) };
+        drop(Weak {
+            data: self.shared,
+            alloc: &self.alloc,
+        });
+    }
+}
+
+impl<T: ?Sized, A: Allocator> AsRef<T> for UniqueTrc<T, A> {
+    fn as_ref(&self) -> &T {
+        self
+    }
+}
+
+impl<T: ?Sized, A: Allocator> AsMut<T> for UniqueTrc<T, A> {
+    fn as_mut(&mut self) -> &mut T {
+        self
+    }
+}
+
+impl<T: ?Sized + Display, A: Allocator> Display for UniqueTrc<T, A> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        Display::fmt(&**self, f)
+    }
+}
+
+impl<T: ?Sized + Debug, A: Allocator> Debug for UniqueTrc<T, A> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        Debug::fmt(&**self, f)
+    }
+}
+
+impl<T: ?Sized, A: Allocator> From<UniqueTrc<T, A>> for Trc<T, A> {
+    /// Freeze a `UniqueTrc<T>` into a `Trc<T>`, see [`UniqueTrc::into_trc`].
+    ///
+    /// # Examples
+    /// ```
+    /// use trc::{Trc, UniqueTrc};
+    ///
+    /// let trc: Trc<i32> = UniqueTrc::new(100).into();
+    /// assert_eq!(*trc, 100);
+    /// ```
+    #[inline]
+    fn from(value: UniqueTrc<T, A>) -> Self {
+        UniqueTrc::into_trc(value)
+    }
+}
+
+impl<T: ?Sized, A: Allocator> From<UniqueTrc<T, A>> for SharedTrc<T, A> {
+    /// Freeze a `UniqueTrc<T>` into a `SharedTrc<T>`, see [`UniqueTrc::into_shared`].
+    ///
+    /// # Examples
+    /// ```
+    /// use trc::{SharedTrc, UniqueTrc};
+    ///
+    /// let shared: SharedTrc<_> = UniqueTrc::new(100).into();
+    /// assert_eq!(*shared, 100);
+    /// ```
+    #[inline]
+    fn from(value: UniqueTrc<T, A>) -> Self {
+        UniqueTrc::into_shared(value)
+    }
+}
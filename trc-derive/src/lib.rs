@@ -0,0 +1,129 @@
+//! `#[derive(Trace)]` for the cycle collector of the [`trc`](https://docs.rs/trc) crate. Enable it with the `derive`
+//! feature of `trc`, which re-exports it as `trc::Trace`.
+//!
+//! The derived implementation traces every field. Fields which cannot contain a `CcTrc`, or whose type does not
+//! implement `Trace`, can be skipped with `#[trace(skip)]`. A skipped `CcTrc` is never part of a collected cycle.
+//!
+//! ## Examples
+//! ```
+//! use std::cell::RefCell;
+//! use trc::{CcTrc, Trace};
+//!
+//! #[derive(Trace)]
+//! struct Node {
+//!     #[trace(skip)]
+//!     name: &'static str,
+//!     next: RefCell<Option<CcTrc<Node>>>,
+//! }
+//!
+//! let node = CcTrc::new(Node { name: "a", next: RefCell::new(None) });
+//! *node.next.borrow_mut() = Some(node.clone());
+//! drop(node);
+//!
+//! trc::collect_cycles();
+//! ```
+
+use proc_macro::TokenStream;
+use proc_macro2::{Ident, Span, TokenStream as TokenStream2};
+use quote::quote;
+use syn::{
+    parse_macro_input, parse_quote, Data, DeriveInput, Error, Fields, GenericParam, Member, Result,
+};
+
+/// Derive `trc::Trace`, tracing every field which is not marked with `#[trace(skip)]`.
+#[proc_macro_derive(Trace, attributes(trace))]
+pub fn derive_trace(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+fn expand(mut input: DeriveInput) -> Result<TokenStream2> {
+    for param in &mut input.generics.params {
+        if let GenericParam::Type(param) = param {
+            param.bounds.push(parse_quote!(::trc::Trace));
+        }
+    }
+
+    let body = match &input.data {
+        Data::Struct(data) => {
+            let (members, bindings) = traced_fields(&data.fields)?;
+            quote! {
+                let Self { #(#members: #bindings,)* .. } = self;
+                #(::trc::Trace::trace(#bindings, tracer);)*
+            }
+        }
+        Data::Enum(data) => {
+            let mut arms = Vec::new();
+            for variant in &data.variants {
+                let name = &variant.ident;
+                let (members, bindings) = traced_fields(&variant.fields)?;
+                arms.push(quote! {
+                    Self::#name { #(#members: #bindings,)* .. } => {
+                        #(::trc::Trace::trace(#bindings, tracer);)*
+                    }
+                });
+            }
+            quote! {
+                match self {
+                    #(#arms)*
+                }
+            }
+        }
+        Data::Union(data) => {
+            return Err(Error::new_spanned(
+                data.union_token,
+                "Trace cannot be derived for unions",
+            ))
+        }
+    };
+
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        unsafe impl #impl_generics ::trc::Trace for #name #ty_generics #where_clause {
+            #[allow(unused_variables)]
+            fn trace(&self, tracer: &mut ::trc::Tracer<'_>) {
+                #body
+            }
+        }
+    })
+}
+
+/// Get the members of the fields which are traced, and the names they are bound to.
+fn traced_fields(fields: &Fields) -> Result<(Vec<Member>, Vec<Ident>)> {
+    let mut members = Vec::new();
+    let mut bindings = Vec::new();
+    for (i, field) in fields.iter().enumerate() {
+        if is_skipped(field)? {
+            continue;
+        }
+
+        members.push(match &field.ident {
+            Some(ident) => Member::Named(ident.clone()),
+            None => Member::Unnamed(i.into()),
+        });
+        bindings.push(Ident::new(&format!("__field{i}"), Span::call_site()));
+    }
+    Ok((members, bindings))
+}
+
+/// Check if a field is marked with `#[trace(skip)]`.
+fn is_skipped(field: &syn::Field) -> Result<bool> {
+    let mut skip = false;
+    for attr in &field.attrs {
+        if !attr.path().is_ident("trace") {
+            continue;
+        }
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("skip") {
+                skip = true;
+                Ok(())
+            } else {
+                Err(meta.error("unsupported trace attribute, expected `skip`"))
+            }
+        })?;
+    }
+    Ok(skip)
+}